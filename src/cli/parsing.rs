@@ -1,4 +1,8 @@
+use crate::ftp_handler::conflict::ConflictPolicy;
+use crate::ftp_handler::proxy::Proxy;
 use clap::Parser;
+use std::path::PathBuf;
+use std::time::Duration;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -22,6 +26,76 @@ pub struct Args {
     /// Password to use for login
     #[arg(short='P', long, default_value_t = String::from(""))]
     pub password: String,
+
+    /// Seconds to wait for the control connection to be established
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub connect_timeout: Option<u64>,
+
+    /// Seconds to wait for a reply on the control connection
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub timeout: Option<u64>,
+
+    /// Seconds a data transfer may stall before it is abandoned
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub data_timeout: Option<u64>,
+
+    /// Seconds of idle time at the prompt before a NOOP is sent (0 disables)
+    #[arg(long, default_value_t = 60)]
+    pub keepalive: u64,
+
+    /// Number of times to retry transfers and connecting after a transient failure
+    #[arg(long, default_value_t = 3)]
+    pub retries: u32,
+
+    /// Seconds to wait before the first retry, doubling on every further attempt
+    #[arg(long, default_value_t = 1.0, value_parser = parse_delay)]
+    pub retry_delay: f64,
+
+    /// Tunnel all connections through a proxy: socks5://[USER:PASS@]HOST[:PORT] or http://[USER:PASS@]HOST[:PORT]
+    #[arg(long)]
+    pub proxy: Option<Proxy>,
+
+    /// Give transferred files the current time instead of the modification time of the original
+    #[arg(long, default_value_t = false)]
+    pub no_preserve_time: bool,
+
+    /// Give uploaded files the permission bits of the local file, if the server supports SITE CHMOD
+    #[arg(long, default_value_t = false)]
+    pub preserve_mode: bool,
+
+    /// What get and put do when the target file exists: ask, skip, overwrite, rename or newer
+    #[arg(long, default_value = "ask")]
+    pub conflict: ConflictPolicy,
+
+    /// Upload files as .NAME.rsftp-part and rename them once complete, so that partial uploads are never seen
+    #[arg(long, default_value_t = false)]
+    pub atomic: bool,
+
+    /// Check every transfer by comparing checksums with the server (HASH, XSHA1, XMD5 or XCRC)
+    #[arg(long, default_value_t = false)]
+    pub verify: bool,
+
+    /// Show the local working directory in the prompt
+    #[arg(long, default_value_t = false)]
+    pub show_local_dir: bool,
+
+    /// Show every command sent to and reply received from the server
+    #[arg(short, long, default_value_t = false)]
+    pub verbose: bool,
+
+    /// Append the control channel dialogue, with timestamps, to this file
+    #[arg(long)]
+    pub log_file: Option<PathBuf>,
+}
+
+/// A number of seconds that makes a valid `Duration`: finite, not negative
+/// and not absurdly large
+fn parse_delay(s: &str) -> Result<f64, String> {
+    let secs = s.parse::<f64>().map_err(|e| e.to_string())?;
+    match Duration::try_from_secs_f64(secs) {
+        Ok(_) => Ok(secs),
+        Err(_) => Err(format!("'{}' is not a usable number of seconds", s)),
+    }
 }
 
 pub fn init() -> Args {
@@ -1,37 +1,246 @@
+use crate::ftp_handler::checksum::Algorithm;
+use crate::ftp_handler::conflict::ConflictPolicy;
+use crate::ftp_handler::filter::{Filter, FindAction};
+use crate::ftp_handler::listing::ListOptions;
+use crate::ftp_handler::session::Session;
+use crate::ftp_handler::settings::Settings;
+use crate::ftp_handler::transfer::TransferMode;
 use crate::ftp_handler::{client::IsFtpStream, commands};
 use ansi_term::Color;
+use regex::Regex;
 use std::fs::File;
 use std::io::{stdout, Write};
+use std::sync::Mutex;
 
-pub fn prompt_user<T: IsFtpStream>(pwd: &str, host: &str, user: &str, fs: &mut T) -> String {
-    let prompt = format!(
-        "[{}]-({}@{})> ",
-        Color::White.bold().paint(pwd),
-        Color::Cyan.bold().paint(user),
-        Color::Blue.bold().paint(host),
-    );
+pub fn prompt_user<T: IsFtpStream>(pwd: &str, session: &Mutex<Session<T>>, settings: &mut Settings) {
+    let local = match std::env::current_dir() {
+        Ok(dir) if settings.show_local_dir => {
+            format!("-[{}]", Color::Green.dimmed().paint(dir.display().to_string()))
+        }
+        _ => String::new(),
+    };
+    let prompt = {
+        let session = session.lock().unwrap();
+        format!(
+            "[{}]-({}@{}){}> ",
+            Color::White.bold().paint(pwd),
+            Color::Cyan.bold().paint(session.user()),
+            Color::Blue.bold().paint(session.host()),
+            local,
+        )
+    };
 
     print!("{}", prompt);
     stdout().flush().unwrap();
 
     let mut buffer = String::new();
-    let mut cache = String::from(user);
     let stdin = std::io::stdin();
     stdin.read_line(&mut buffer).unwrap();
-    input_handler(buffer.as_str(), fs, &mut cache);
-    cache
+
+    let cmd = buffer.split_whitespace().collect::<Vec<&str>>();
+    if cmd.first() == Some(&"edit") {
+        edit_handler(&cmd, session, settings);
+        return;
+    }
+
+    let mut session = session.lock().unwrap();
+    // Lost by the prompt's PWD or a keepalive NOOP rather than by this
+    // command, so reconnect before running it instead of after
+    if session.connection_lost() {
+        reconnect(&mut session);
+    }
+    input_handler(buffer.as_str(), &mut *session, settings);
+
+    if session.connection_lost() && reconnect(&mut session) {
+        if is_replayable(buffer.as_str()) {
+            input_handler(buffer.as_str(), &mut *session, settings);
+        } else {
+            println!(
+                "{}",
+                Color::Yellow.paint(format!(
+                    "[*] '{}' may not have completed, run it again if needed",
+                    buffer.trim()
+                ))
+            );
+        }
+    }
+}
+
+/// `edit` lets go of the session while the editor is open, so that the
+/// keepalive can hold the connection open for however long that takes
+fn edit_handler<T: IsFtpStream>(cmd: &[&str], session: &Mutex<Session<T>>, settings: &Settings) {
+    let (file, force) = match cmd[1..] {
+        [file] => (file, false),
+        ["-f", file] | [file, "-f"] => (file, true),
+        _ => {
+            println!("{}", Color::Red.paint("[-] Expected [-f] REMOTE_FILE"));
+            commands::help("edit");
+            return;
+        }
+    };
+    let edit = {
+        let mut session = session.lock().unwrap();
+        if session.connection_lost() && !reconnect(&mut session) {
+            return;
+        }
+        commands::edit_fetch(&mut *session, file, settings)
+    };
+    let Some(edit) = edit.and_then(commands::edit_run) else { return };
+
+    let mut session = session.lock().unwrap();
+    // Find out whether the connection outlived the editor before relying on it
+    let _ = session.noop();
+    if session.connection_lost() && !reconnect(&mut session) {
+        edit.keep();
+        return;
+    }
+    commands::edit_store(&mut *session, edit, force, settings);
+}
+
+fn reconnect<T: IsFtpStream>(session: &mut Session<T>) -> bool {
+    println!(
+        "{}",
+        Color::Yellow.paint("[*] Connection lost, reconnecting...")
+    );
+    match session.reconnect() {
+        Ok(()) => true,
+        Err(e) => {
+            println!("{}", Color::Red.paint(format!("[-] Reconnect failed: {}", e)));
+            false
+        }
+    }
+}
+
+/// Commands that only read from the server, so that running them again after
+/// a reconnect can do no harm. Local and shell commands, pipes and anything
+/// that changes files may have partly happened and are never repeated.
+const REPLAYABLE: &[&str] = &[
+    "pwd", "cd", "cwd", "cdup", "ls", "dir", "size", "mdtm", "modtime", "tree", "du", "feat",
+    "system", "rhelp", "hash", "noop",
+];
+
+fn is_replayable(command: &str) -> bool {
+    !command.contains('|')
+        && command
+            .split_whitespace()
+            .next()
+            .is_none_or(|cmd| REPLAYABLE.contains(&cmd))
+}
+
+/// Send the output of the commands that support it to a local command with
+/// `|`. Anything else is not a pipe, e.g. a `|` inside a `quote`, and is left
+/// to `input_handler` by returning `false`.
+fn pipe_handler<T: IsFtpStream>(command: &str, pipe: &str, fs: &mut T, settings: &mut Settings) -> bool {
+    let cmd = command.split_whitespace().collect::<Vec<&str>>();
+    if !matches!(cmd.first(), Some(&"get") | Some(&"ls") | Some(&"dir")) {
+        return false;
+    }
+    if pipe.is_empty() {
+        println!("{}", Color::Red.paint("[-] Missing command after |"));
+        commands::help("|");
+        return true;
+    }
+    match cmd[0] {
+        "get" if cmd.len() != 2 => {
+            println!("{}", Color::Red.paint("[-] Exactly one remote file can be piped"));
+            commands::help("|");
+        }
+        "get" => commands::get_piped(fs, cmd[1], pipe, settings),
+        _ => {
+            if let Some((target, opts)) = list_args(&cmd) {
+                commands::ls_piped(fs, target, &opts, pipe);
+            }
+        }
+    }
+    true
 }
 
-fn input_handler<T: IsFtpStream>(command: &str, fs: &mut T, cache: &mut String) {
-    if command.len() == 0 || command == "" || command == "\n" {
+/// Split `ls`/`lls` arguments into the directory, "." by default, and the
+/// listing flags; `None` after reporting an unknown flag
+fn list_args<'a>(cmd: &[&'a str]) -> Option<(&'a str, ListOptions)> {
+    let mut opts = ListOptions::default();
+    let mut target = ".";
+    for arg in cmd.iter().skip(1) {
+        if arg.starts_with('-') && arg.len() > 1 {
+            if let Err(c) = opts.parse_flags(arg) {
+                println!("{}", Color::Red.paint(format!("[-] Unknown flag: -{}", c)));
+                commands::help(cmd[0]);
+                return None;
+            }
+        } else {
+            target = arg;
+        }
+    }
+    Some((target, opts))
+}
+
+/// The `on`/`off` argument of a setting that is toggled without one; `None`
+/// after reporting anything else
+fn switch_arg(cmd: &[&str]) -> Option<Option<bool>> {
+    match cmd.get(1) {
+        None => Some(None),
+        Some(&"on") => Some(Some(true)),
+        Some(&"off") => Some(Some(false)),
+        Some(arg) => {
+            println!("{}", Color::Red.paint(format!("[-] Expected on or off, not {}", arg)));
+            commands::help(cmd[0]);
+            None
+        }
+    }
+}
+
+/// Split `get`/`put` arguments into the `-c POLICY` conflict policy, the
+/// global one by default, whether `get -p` was given and the file names.
+/// `--` ends the options, for files whose names start with a dash. `None`
+/// after reporting an unknown policy.
+fn transfer_args<'a>(cmd: &[&'a str], settings: &Settings) -> Option<(ConflictPolicy, bool, Vec<&'a str>)> {
+    let mut policy = settings.conflict;
+    let mut make_parents = false;
+    let mut args = Vec::new();
+    let mut rest = cmd.iter().skip(1);
+    while let Some(&arg) = rest.next() {
+        match arg {
+            "--" => {
+                args.extend(rest);
+                break;
+            }
+            "-p" if cmd[0] == "get" => make_parents = true,
+            "-c" => match rest.next().map(|p| p.parse::<ConflictPolicy>()) {
+                Some(Ok(p)) => policy = p,
+                Some(Err(e)) => {
+                    println!("{}", Color::Red.paint(format!("[-] {}", e)));
+                    commands::help(cmd[0]);
+                    return None;
+                }
+                None => {
+                    println!("{}", Color::Red.paint("[-] Expected a conflict policy after -c"));
+                    commands::help(cmd[0]);
+                    return None;
+                }
+            },
+            _ => args.push(arg),
+        }
+    }
+    Some((policy, make_parents, args))
+}
+
+fn input_handler<T: IsFtpStream>(command: &str, fs: &mut T, settings: &mut Settings) {
+    if command.is_empty() || command == "\n" {
         commands::noop(fs);
         return;
     }
 
-    let cmd = command
-        .split_whitespace()
-        .into_iter()
-        .collect::<Vec<&str>>();
+    if let Some(shell_command) = command.trim_start().strip_prefix('!') {
+        commands::shell(shell_command.trim());
+        return;
+    }
+    if let Some((command, pipe)) = command.split_once('|') {
+        if pipe_handler(command, pipe.trim(), fs, settings) {
+            return;
+        }
+    }
+
+    let cmd = command.split_whitespace().collect::<Vec<&str>>();
 
     // check the command first!
     match cmd[0] {
@@ -64,12 +273,274 @@ fn input_handler<T: IsFtpStream>(command: &str, fs: &mut T, cache: &mut String)
             commands::mkdir(fs, pathname);
         }
         "ls" | "dir" => {
+            if let Some((target, opts)) = list_args(&cmd) {
+                commands::ls(fs, target, &opts);
+            }
+        }
+        "lls" => {
+            if let Some((target, opts)) = list_args(&cmd) {
+                commands::lls(target, &opts);
+            }
+        }
+        "lcd" => {
+            commands::lcd(settings, cmd.get(1).copied());
+        }
+        "lmkdir" => {
             if cmd.len() < 2 {
-                commands::ls(fs, ".");
+                println!("{}", Color::Red.paint("[-] Directory not specified"));
+                commands::help("lmkdir");
                 return;
             }
-            let target = cmd[1];
-            commands::ls(fs, target);
+            for dir in &cmd[1..] {
+                commands::lmkdir(dir);
+            }
+        }
+        "lrm" => {
+            if cmd.len() < 2 {
+                println!("{}", Color::Red.paint("[-] Target file not specified"));
+                commands::help("lrm");
+                return;
+            }
+            for file in &cmd[1..] {
+                commands::lrm(file);
+            }
+        }
+        "tree" => {
+            let mut target = ".";
+            let mut max_depth = None;
+            let mut dirs_only = false;
+            let mut args = cmd.iter().skip(1);
+            while let Some(arg) = args.next() {
+                match *arg {
+                    "-d" => dirs_only = true,
+                    "-L" => match args.next().and_then(|d| d.parse::<usize>().ok()) {
+                        Some(depth) if depth > 0 => max_depth = Some(depth),
+                        _ => {
+                            println!("{}", Color::Red.paint("[-] -L needs a depth greater than 0"));
+                            commands::help("tree");
+                            return;
+                        }
+                    },
+                    _ => target = arg,
+                }
+            }
+            commands::tree(fs, target, max_depth, dirs_only);
+        }
+        "du" => {
+            let mut target = ".";
+            let mut summarize = false;
+            let mut human = false;
+            let mut max_depth = None;
+            let mut args = cmd.iter().skip(1);
+            while let Some(arg) = args.next() {
+                let depth = match arg.strip_prefix("--max-depth") {
+                    Some("") => args.next().copied(),
+                    Some(value) => value.strip_prefix('='),
+                    None => None,
+                };
+                if arg.starts_with("--max-depth") {
+                    match depth.and_then(|d| d.parse::<usize>().ok()) {
+                        Some(depth) => max_depth = Some(depth),
+                        None => {
+                            println!("{}", Color::Red.paint("[-] --max-depth needs a number"));
+                            commands::help("du");
+                            return;
+                        }
+                    }
+                } else if arg.starts_with('-') && arg.len() > 1 {
+                    for c in arg.chars().skip(1) {
+                        match c {
+                            's' => summarize = true,
+                            'h' => human = true,
+                            _ => {
+                                println!("{}", Color::Red.paint(format!("[-] Unknown flag: -{}", c)));
+                                commands::help("du");
+                                return;
+                            }
+                        }
+                    }
+                } else {
+                    target = arg;
+                }
+            }
+            commands::du(fs, target, summarize, human, max_depth);
+        }
+        "find" => {
+            let mut target = ".";
+            let mut filter = Filter::default();
+            let mut action = FindAction::Print;
+            let mut force = false;
+            let mut args = cmd.iter().skip(1);
+            while let Some(arg) = args.next() {
+                let valid = match *arg {
+                    "-get" => {
+                        action = FindAction::Get;
+                        true
+                    }
+                    "-delete" => {
+                        action = FindAction::Delete;
+                        true
+                    }
+                    "-f" => {
+                        force = true;
+                        true
+                    }
+                    "-print" => {
+                        action = FindAction::Print;
+                        true
+                    }
+                    "-name" => {
+                        filter.name = args.next().map(|p| p.to_string());
+                        filter.name.is_some()
+                    }
+                    "-type" => {
+                        filter.kind = args.next().and_then(|t| Filter::parse_type(t));
+                        filter.kind.is_some()
+                    }
+                    "-size" => {
+                        filter.size = args.next().and_then(|s| Filter::parse_size(s));
+                        filter.size.is_some()
+                    }
+                    "-mtime" => {
+                        filter.mtime = args.next().and_then(|m| Filter::parse_mtime(m));
+                        filter.mtime.is_some()
+                    }
+                    a if !a.starts_with('-') => {
+                        target = a;
+                        true
+                    }
+                    _ => false,
+                };
+                if !valid {
+                    println!("{}", Color::Red.paint(format!("[-] Invalid find expression at: {}", arg)));
+                    commands::help("find");
+                    return;
+                }
+            }
+            commands::find(fs, target, &filter, action, force, settings);
+        }
+        "chmod" => {
+            if cmd.len() < 3 {
+                println!("{}", Color::Red.paint("[-] Mode/remote file not specified properly"));
+                commands::help("chmod");
+                return;
+            }
+            if u32::from_str_radix(cmd[1], 8).is_err() {
+                println!("{}", Color::Red.paint(format!("[-] Invalid mode: {}", cmd[1])));
+                return;
+            }
+            for file in &cmd[2..] {
+                commands::chmod(fs, cmd[1], file);
+            }
+        }
+        "site" => {
+            if cmd.len() < 2 {
+                println!("{}", Color::Red.paint("[-] SITE command not specified"));
+                commands::help("site");
+                return;
+            }
+            commands::site(fs, cmd[1..].join(" ").as_str());
+        }
+        "quote" | "literal" => {
+            if cmd.len() < 2 {
+                println!("{}", Color::Red.paint("[-] Command not specified"));
+                commands::help("quote");
+                return;
+            }
+            let raw = command.trim_start()[cmd[0].len()..].trim();
+            commands::quote(fs, raw);
+        }
+        "type" => {
+            let mode = match cmd.get(1) {
+                None => None,
+                Some(&"binary") | Some(&"image") => Some(TransferMode::Binary),
+                Some(&"ascii") => Some(TransferMode::Ascii),
+                Some(&"auto") => Some(TransferMode::Auto),
+                Some(arg) => {
+                    println!("{}", Color::Red.paint(format!("[-] Unknown transfer mode: {}", arg)));
+                    commands::help("type");
+                    return;
+                }
+            };
+            commands::transfer_mode(settings, mode);
+        }
+        "conflict" => {
+            let policy = match cmd.get(1).map(|p| p.parse::<ConflictPolicy>()) {
+                None => None,
+                Some(Ok(policy)) => Some(policy),
+                Some(Err(e)) => {
+                    println!("{}", Color::Red.paint(format!("[-] {}", e)));
+                    commands::help("conflict");
+                    return;
+                }
+            };
+            commands::conflict_policy(settings, policy);
+        }
+        "ascii" => {
+            commands::transfer_mode(settings, Some(TransferMode::Ascii));
+        }
+        "binary" => {
+            commands::transfer_mode(settings, Some(TransferMode::Binary));
+        }
+        "feat" => {
+            commands::feat(fs);
+        }
+        "system" => {
+            commands::system(fs);
+        }
+        "rhelp" => {
+            commands::rhelp(fs, cmd.get(1).copied());
+        }
+        "debug" => {
+            if let Some(enable) = switch_arg(&cmd) {
+                commands::debug(enable);
+            }
+        }
+        "atomic" => {
+            if let Some(enable) = switch_arg(&cmd) {
+                commands::atomic_uploads(settings, enable);
+            }
+        }
+        "verify" => {
+            if let Some(enable) = switch_arg(&cmd) {
+                commands::verify_transfers(settings, enable);
+            }
+        }
+        "hash" => {
+            let (algorithm, args) = match cmd.get(1) {
+                Some(&"-a") => match cmd.get(2).map(|name| Algorithm::parse(name)) {
+                    Some(Some(algorithm)) => (Some(algorithm), &cmd[3..]),
+                    _ => {
+                        println!("{}", Color::Red.paint("[-] Expected sha256, sha1, md5 or crc32 after -a"));
+                        commands::help("hash");
+                        return;
+                    }
+                },
+                _ => (None, &cmd[1..]),
+            };
+            match args {
+                [remote] => commands::hash(fs, remote, None, algorithm),
+                [remote, local] => commands::hash(fs, remote, Some(local), algorithm),
+                _ => {
+                    println!("{}", Color::Red.paint("[-] Target file not specified properly"));
+                    commands::help("hash");
+                }
+            }
+        }
+        "rename" | "mv" => {
+            if cmd.len() < 3 || (cmd[1] == "-r" && cmd.len() < 4) {
+                println!("{}", Color::Red.paint("[-] Source/destination not specified properly"));
+                commands::help("rename");
+                return;
+            }
+            if cmd[1] == "-r" {
+                match Regex::new(cmd[2]) {
+                    Ok(pattern) => commands::rename_matching(fs, &pattern, cmd[3]),
+                    Err(e) => println!("{}", Color::Red.paint(format!("[-] Invalid pattern: {}", e))),
+                }
+            } else {
+                commands::rename(fs, cmd[1], cmd[2]);
+            }
         }
         "append" => {
             if cmd.len() < 3 {
@@ -88,7 +559,6 @@ fn input_handler<T: IsFtpStream>(command: &str, fs: &mut T, cache: &mut String)
                 }
                 Err(e) => {
                     println!("{}", Color::Red.paint(format!("[-] {}", e)));
-                    return;
                 }
             }
         }
@@ -115,79 +585,85 @@ fn input_handler<T: IsFtpStream>(command: &str, fs: &mut T, cache: &mut String)
             }
             commands::rmdir(fs, cmd[1]);
         }
-        "size" => {
+        "mdtm" | "modtime" => {
             if cmd.len() < 2 {
                 println!("{}", Color::Red.paint("[-] Target file not specified"));
-                commands::help("size");
-                return;
-            }
-            if cmd.len() == 2 {
-                commands::size(fs, cmd[1]);
+                commands::help("mdtm");
                 return;
             }
-
-            for i in cmd.iter().skip(1) {
-                commands::size(fs, i);
+            for file in &cmd[1..] {
+                commands::mdtm(fs, file);
             }
         }
-        "get" => {
+        "cat" => {
             if cmd.len() < 2 {
                 println!("{}", Color::Red.paint("[-] Target file not specified"));
-                commands::help("get");
+                commands::help("cat");
                 return;
             }
-            if cmd.len() == 2 {
-                match File::create(cmd[1]) {
-                    Ok(mut file) => {
-                        commands::get(fs, cmd[1], &mut file);
-                    }
-                    Err(e) => {
-                        println!("{}", Color::Red.paint(format!("[-] {}", e)));
-                        return;
-                    }
-                }
-                return;
+            for file in &cmd[1..] {
+                commands::cat(fs, file, settings);
             }
-
-            match File::open(cmd[2]) {
-                Ok(mut file) => {
-                    commands::get(fs, cmd[1], &mut file);
-                }
-                Err(e) => {
-                    println!("{}", Color::Red.paint(format!("[-] {}", e)));
-                    return;
+        }
+        "head" | "tail" => {
+            let (lines, file) = match cmd[1..] {
+                [file] => (Some(10), file),
+                ["-n", n, file] => (n.parse::<usize>().ok(), file),
+                _ => (None, ""),
+            };
+            match lines {
+                Some(lines) if cmd[0] == "head" => commands::head(fs, file, lines, settings),
+                Some(lines) => commands::tail(fs, file, lines),
+                None => {
+                    println!("{}", Color::Red.paint("[-] Expected [-n LINES] REMOTE_FILE"));
+                    commands::help(cmd[0]);
                 }
             }
         }
-        "put" => {
+        "page" | "less" => {
+            if cmd.len() != 2 {
+                println!("{}", Color::Red.paint("[-] Target file not specified"));
+                commands::help("page");
+                return;
+            }
+            commands::page(fs, cmd[1], settings);
+        }
+        "size" => {
             if cmd.len() < 2 {
                 println!("{}", Color::Red.paint("[-] Target file not specified"));
-                commands::help("put");
+                commands::help("size");
                 return;
             }
             if cmd.len() == 2 {
-                match File::open(cmd[1]) {
-                    Ok(mut file) => {
-                        commands::put(fs, &mut file, cmd[1]);
-                    }
-                    Err(e) => {
-                        println!("{}", Color::Red.paint(format!("[-] {}", e)));
-                        return;
-                    }
-                }
+                commands::size(fs, cmd[1]);
                 return;
             }
 
-            match File::open(cmd[1]) {
-                Ok(mut file) => {
-                    commands::put(fs, &mut file, cmd[2]);
+            for i in cmd.iter().skip(1) {
+                commands::size(fs, i);
+            }
+        }
+        "get" | "put" => {
+            let Some((policy, make_parents, args)) = transfer_args(&cmd, settings) else { return };
+            let (source, target) = match args[..] {
+                [source] => (source, None),
+                [source, target] => (source, Some(target)),
+                [] => {
+                    println!("{}", Color::Red.paint("[-] Target file not specified"));
+                    commands::help(cmd[0]);
+                    return;
                 }
-                Err(e) => {
-                    println!("{}", Color::Red.paint(format!("[-] {}", e)));
+                _ => {
+                    println!("{}", Color::Red.paint("[-] Too many arguments"));
+                    commands::help(cmd[0]);
                     return;
                 }
+            };
+            if cmd[0] == "get" {
+                commands::get(fs, source, target, make_parents, policy, settings);
+            } else {
+                commands::put(fs, source, target.unwrap_or(source), policy, settings);
             }
-            return;
         }
         "user" => {
             if cmd.len() < 2 {
@@ -196,14 +672,13 @@ fn input_handler<T: IsFtpStream>(command: &str, fs: &mut T, cache: &mut String)
                 return;
             }
             let mut buffer = String::new();
-            stdout().flush();
             print!(
                 "{}",
                 Color::White.bold().paint("Enter passwords for user: ")
             );
-            std::io::stdout().flush();
+            let _ = stdout().flush();
             std::io::stdin().read_line(&mut buffer).unwrap();
-            commands::user(fs, cmd[1], buffer.as_str(), cache);
+            commands::user(fs, cmd[1], buffer.trim_end_matches(['\r', '\n']));
         }
         "noop" => {
             commands::noop(fs);
@@ -214,7 +689,7 @@ fn input_handler<T: IsFtpStream>(command: &str, fs: &mut T, cache: &mut String)
         }
         "help" => {
             if cmd.len() < 2 {
-                commands::print_available_commands();    
+                commands::print_available_commands();
             } else {
                 commands::help(cmd[1]);
             }
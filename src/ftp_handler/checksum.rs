@@ -0,0 +1,217 @@
+use crate::ftp_handler::client::{self, IsFtpStream};
+use md5::Md5;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read};
+use suppaftp::{FtpError, FtpResult, Status};
+
+/// A digest that can be computed locally and asked of a server
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Algorithm {
+    Sha256,
+    Sha1,
+    Md5,
+    Crc32,
+}
+
+/// Tried in this order when the server only has the X commands
+const FALLBACK_ORDER: [Algorithm; 4] = [Algorithm::Sha256, Algorithm::Sha1, Algorithm::Md5, Algorithm::Crc32];
+
+impl Algorithm {
+    /// The name used by the HASH command
+    pub fn name(&self) -> &'static str {
+        match self {
+            Algorithm::Sha256 => "SHA-256",
+            Algorithm::Sha1 => "SHA-1",
+            Algorithm::Md5 => "MD5",
+            Algorithm::Crc32 => "CRC32",
+        }
+    }
+
+    /// Accepts the HASH names as well as the usual spellings without a dash
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.replace('-', "").to_ascii_lowercase().as_str() {
+            "sha256" => Some(Algorithm::Sha256),
+            "sha1" => Some(Algorithm::Sha1),
+            "md5" => Some(Algorithm::Md5),
+            "crc32" | "crc" => Some(Algorithm::Crc32),
+            _ => None,
+        }
+    }
+
+    /// The non-standard command that predates HASH
+    fn x_command(&self) -> &'static str {
+        match self {
+            Algorithm::Sha256 => "XSHA256",
+            Algorithm::Sha1 => "XSHA1",
+            Algorithm::Md5 => "XMD5",
+            Algorithm::Crc32 => "XCRC",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Checksum {
+    pub algorithm: Algorithm,
+    /// Lowercase hex
+    pub value: String,
+}
+
+impl Checksum {
+    fn new(algorithm: Algorithm, value: &str) -> Self {
+        Checksum {
+            algorithm,
+            value: value.to_ascii_lowercase(),
+        }
+    }
+
+    pub fn matches(&self, other: &Checksum) -> bool {
+        if self.algorithm != other.algorithm {
+            return false;
+        }
+        match self.algorithm {
+            // Some servers leave out the leading zeros of a CRC
+            Algorithm::Crc32 => {
+                let a = u32::from_str_radix(&self.value, 16);
+                a.is_ok() && a == u32::from_str_radix(&other.value, 16)
+            }
+            _ => self.value == other.value,
+        }
+    }
+}
+
+/// A transferred file whose checksum differs from the one on the other end.
+/// It is carried in a `ConnectionError`, as suppaftp has no variant for
+/// errors of its callers, but is no fault of the connection.
+#[derive(Debug)]
+pub struct Mismatch {
+    pub file: String,
+    pub local: Checksum,
+    pub remote: Checksum,
+}
+
+impl Mismatch {
+    pub fn into_error(self) -> FtpError {
+        FtpError::ConnectionError(io::Error::new(io::ErrorKind::InvalidData, self))
+    }
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} checksum mismatch for {}: local {}, remote {}",
+            self.remote.algorithm.name(),
+            self.file,
+            self.local.value,
+            self.remote.value
+        )
+    }
+}
+
+impl Error for Mismatch {}
+
+pub fn is_mismatch(e: &io::Error) -> bool {
+    e.get_ref().is_some_and(|inner| inner.is::<Mismatch>())
+}
+
+/// Checksum everything `reader` has left
+pub fn local<R: Read>(reader: &mut R, algorithm: Algorithm) -> io::Result<Checksum> {
+    let value = match algorithm {
+        Algorithm::Sha256 => digest::<Sha256, R>(reader)?,
+        Algorithm::Sha1 => digest::<Sha1, R>(reader)?,
+        Algorithm::Md5 => digest::<Md5, R>(reader)?,
+        Algorithm::Crc32 => {
+            let mut hasher = crc32fast::Hasher::new();
+            feed(reader, |chunk| hasher.update(chunk))?;
+            format!("{:08x}", hasher.finalize())
+        }
+    };
+    Ok(Checksum::new(algorithm, &value))
+}
+
+fn digest<D: Digest, R: Read>(reader: &mut R) -> io::Result<String> {
+    let mut hasher = D::new();
+    feed(reader, |chunk| hasher.update(chunk))?;
+    Ok(hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+fn feed<R: Read, F: FnMut(&[u8])>(reader: &mut R, mut consume: F) -> io::Result<()> {
+    let mut buf = [0u8; 8192];
+    loop {
+        match reader.read(&mut buf)? {
+            0 => return Ok(()),
+            n => consume(&buf[..n]),
+        }
+    }
+}
+
+/// Ask the server for the checksum of `remote_file`, using `preferred` if
+/// given and otherwise whatever the server picks. HASH is used where FEAT
+/// lists it; otherwise the older XSHA256/XSHA1/XMD5/XCRC commands are tried.
+/// `None` if the server knows none of them.
+pub fn remote<T: IsFtpStream>(
+    fs: &mut T,
+    remote_file: &str,
+    preferred: Option<Algorithm>,
+) -> FtpResult<Option<Checksum>> {
+    if let Some(offered) = client::feature_value(fs, "HASH") {
+        let offered = offered
+            .split(';')
+            .filter_map(|name| Algorithm::parse(name.trim_end_matches('*')))
+            .collect::<Vec<_>>();
+        match preferred {
+            // The choice is kept for the rest of the connection
+            Some(algorithm) if offered.contains(&algorithm) => {
+                fs.custom_command(&format!("OPTS HASH {}", algorithm.name()), &[Status::CommandOk])?;
+            }
+            Some(_) => return Ok(None),
+            None => {}
+        }
+        return hash(fs, remote_file).map(Some);
+    }
+
+    let candidates = match preferred {
+        Some(algorithm) => vec![algorithm],
+        None => FALLBACK_ORDER.to_vec(),
+    };
+    for algorithm in candidates {
+        let command = format!("{} {}", algorithm.x_command(), remote_file);
+        match fs.custom_command(&command, &[Status::RequestedFileActionOk, Status::File]) {
+            Ok(response) => {
+                let text = String::from_utf8_lossy(&response.body).into_owned();
+                // Some servers repeat the file name along with the value
+                let value = text
+                    .split_whitespace()
+                    .skip(1)
+                    .filter(|word| word.chars().all(|c| c.is_ascii_hexdigit()))
+                    .last()
+                    .ok_or(FtpError::BadResponse)?;
+                return Ok(Some(Checksum::new(algorithm, value)));
+            }
+            Err(FtpError::UnexpectedResponse(r)) if is_unknown_command(r.status) => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(None)
+}
+
+/// `HASH` replies name the algorithm, the byte range covered and the value:
+/// `213 SHA-256 0-1024 9f86d08... file.txt`
+fn hash<T: IsFtpStream>(fs: &mut T, remote_file: &str) -> FtpResult<Checksum> {
+    let response = fs.custom_command(&format!("HASH {}", remote_file), &[Status::File])?;
+    let text = String::from_utf8_lossy(&response.body).into_owned();
+    let mut words = text.split_whitespace().skip(1);
+    let algorithm = words.next().and_then(Algorithm::parse).ok_or(FtpError::BadResponse)?;
+    let value = words.nth(1).ok_or(FtpError::BadResponse)?;
+    Ok(Checksum::new(algorithm, value))
+}
+
+fn is_unknown_command(status: Status) -> bool {
+    matches!(
+        status,
+        Status::BadCommand | Status::NotImplemented | Status::NotImplementedParameter
+    )
+}
@@ -1,31 +1,148 @@
-use std::io::Read;
+use std::io::{Error, ErrorKind, Read};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+use chrono::NaiveDateTime;
 use suppaftp::native_tls::TlsConnector;
 use suppaftp::FtpStream;
-use suppaftp::{FtpResult, NativeTlsConnector, NativeTlsFtpStream};
+use suppaftp::types::{Features, FileType, Mode, Response};
+use suppaftp::{FtpError, FtpResult, NativeTlsConnector, NativeTlsFtpStream, Status};
 
-pub fn connect(host: String, port: u16, username: &str, password: &str) -> FtpStream {
-    #[allow(unused_mut)]
-    let mut ftp_stream = FtpStream::connect(format!("{}:{}", host, port)).unwrap();
-    let _ = ftp_stream.login(username, password).unwrap();
+use crate::ftp_handler::proxy::Proxy;
+use crate::ftp_handler::retry::RetryPolicy;
 
-    ftp_stream
+/// Everything needed to (re-)establish a logged in session with a server
+#[derive(Clone, Debug)]
+pub struct ConnectionInfo {
+    pub host: String,
+    pub port: u16,
+    pub username: String,
+    pub password: String,
+    pub connect_timeout: Option<Duration>,
+    pub timeout: Option<Duration>,
+    pub data_timeout: Option<Duration>,
+    pub retry: RetryPolicy,
+    pub proxy: Option<Proxy>,
 }
 
-pub fn connect_tls(host: String, port: u16, username: &str, password: &str) -> NativeTlsFtpStream {
-    #[allow(unused_mut)]
-    let mut ftp_stream = NativeTlsFtpStream::connect(format!("{}:{}", host, port)).unwrap();
-    let mut ftp_stream = ftp_stream
-        .into_secure(
-            NativeTlsConnector::from(TlsConnector::new().unwrap()),
-            host.as_str(),
-        )
-        .unwrap();
-    ftp_stream.login(username, password).unwrap();
-
-    ftp_stream
+pub fn connect(info: &ConnectionInfo) -> FtpResult<FtpStream> {
+    let data_info = info.clone();
+    let mut ftp_stream = FtpStream::connect_with_stream(control_stream(info)?)?
+        .passive_stream_builder(move |addr| data_stream(addr, &data_info));
+    ftp_stream.login(info.username.as_str(), info.password.as_str())?;
+
+    Ok(ftp_stream)
+}
+
+pub fn connect_tls(info: &ConnectionInfo) -> FtpResult<NativeTlsFtpStream> {
+    let data_info = info.clone();
+    let ftp_stream = NativeTlsFtpStream::connect_with_stream(control_stream(info)?)?
+        .passive_stream_builder(move |addr| data_stream(addr, &data_info));
+    let connector = TlsConnector::new().map_err(|e| FtpError::SecureError(e.to_string()))?;
+    let mut ftp_stream =
+        ftp_stream.into_secure(NativeTlsConnector::from(connector), info.host.as_str())?;
+    ftp_stream.login(info.username.as_str(), info.password.as_str())?;
+
+    Ok(ftp_stream)
+}
+
+/// Open the control connection, through the proxy if there is one, honouring
+/// the connect and reply timeouts
+fn control_stream(info: &ConnectionInfo) -> FtpResult<TcpStream> {
+    let stream = match &info.proxy {
+        Some(proxy) => proxy
+            .connect(info.host.as_str(), info.port, info.connect_timeout)
+            .map_err(FtpError::ConnectionError)?,
+        None => direct_stream(info)?,
+    };
+    stream
+        .set_read_timeout(info.timeout)
+        .and_then(|_| stream.set_write_timeout(info.timeout))
+        .map_err(FtpError::ConnectionError)?;
+    Ok(stream)
+}
+
+fn direct_stream(info: &ConnectionInfo) -> FtpResult<TcpStream> {
+    let addrs = (info.host.as_str(), info.port)
+        .to_socket_addrs()
+        .map_err(FtpError::ConnectionError)?;
+
+    let mut last_error = Error::new(ErrorKind::NotFound, "host did not resolve to any address");
+    for addr in addrs {
+        match open_stream(addr, info.connect_timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = e,
+        }
+    }
+    Err(FtpError::ConnectionError(last_error))
+}
+
+/// Open a passive mode data connection, through the proxy if there is one,
+/// honouring the connect and stall timeouts
+fn data_stream(addr: SocketAddr, info: &ConnectionInfo) -> FtpResult<TcpStream> {
+    let stream = match &info.proxy {
+        Some(proxy) => proxy.connect(
+            addr.ip().to_string().as_str(),
+            addr.port(),
+            info.connect_timeout,
+        ),
+        None => open_stream(addr, info.connect_timeout),
+    }
+    .map_err(FtpError::ConnectionError)?;
+    stream
+        .set_read_timeout(info.data_timeout)
+        .and_then(|_| stream.set_write_timeout(info.data_timeout))
+        .map_err(FtpError::ConnectionError)?;
+    Ok(stream)
+}
+
+pub fn open_stream(addr: SocketAddr, timeout: Option<Duration>) -> std::io::Result<TcpStream> {
+    match timeout {
+        Some(timeout) => TcpStream::connect_timeout(&addr, timeout),
+        None => TcpStream::connect(addr),
+    }
+}
+
+/// Whether an error was caused by one of the configured timeouts expiring
+pub fn is_timeout(e: &FtpError) -> bool {
+    match e {
+        FtpError::ConnectionError(e) => {
+            matches!(e.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock)
+        }
+        _ => false,
+    }
+}
+
+/// Whether the server listed `name` in its FEAT reply
+pub fn has_feature<T: IsFtpStream>(fs: &mut T, name: &str) -> bool {
+    fs.feat()
+        .map(|features| features.keys().any(|k| k.eq_ignore_ascii_case(name)))
+        .unwrap_or(false)
+}
+
+/// The parameters the server listed with feature `name`, if it has it
+pub fn feature_value<T: IsFtpStream>(fs: &mut T, name: &str) -> Option<String> {
+    fs.feat()
+        .ok()?
+        .into_iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.unwrap_or_default())
 }
 
+/// Replies a SITE command may succeed with; which one depends on the
+/// command and the server. 202 means the command was ignored, so it is not
+/// one of them.
+const SITE_OK: &[Status] = &[
+    Status::CommandOk,
+    Status::Directory,
+    Status::File,
+    Status::Help,
+    Status::RequestedFileActionOk,
+];
+
 pub trait IsFtpStream {
+    fn open(info: &ConnectionInfo) -> FtpResult<Self>
+    where
+        Self: Sized;
     fn pwd(&mut self) -> FtpResult<String>;
     fn cdup(&mut self) -> FtpResult<()>;
     fn quit(&mut self) -> FtpResult<()>;
@@ -33,19 +150,76 @@ pub trait IsFtpStream {
     fn cwd<S: AsRef<str>>(&mut self, path: S) -> FtpResult<()>;
     fn mkdir<S: AsRef<str>>(&mut self, pathname: S) -> FtpResult<()>;
     fn list(&mut self, pathname: Option<&str>) -> FtpResult<Vec<String>>;
+    fn mlsd(&mut self, pathname: Option<&str>) -> FtpResult<Vec<String>>;
+    fn feat(&mut self) -> FtpResult<Features>;
     fn size<S: AsRef<str>>(&mut self, pathname: S) -> FtpResult<usize>;
+    fn mdtm<S: AsRef<str>>(&mut self, pathname: S) -> FtpResult<NaiveDateTime>;
     fn append_file<R: Read>(&mut self, filename: &str, r: &mut R) -> FtpResult<u64>;
 
     fn rm<S: AsRef<str>>(&mut self, filename: S) -> FtpResult<()>;
     fn rmdir<S: AsRef<str>>(&mut self, pathname: S) -> FtpResult<()>;
+    fn rename<S: AsRef<str>>(&mut self, from_name: S, to_name: S) -> FtpResult<()>;
     fn retr<F, D>(&mut self, file_name: &str, reader: F) -> FtpResult<D>
     where
         F: FnMut(&mut dyn Read) -> FtpResult<D>;
+    /// Like `retr`, except that `reader` may stop early by returning `false`,
+    /// or by failing, and the rest of the transfer is then cancelled with ABOR
+    fn retr_until<F>(&mut self, file_name: &str, reader: F) -> FtpResult<()>
+    where
+        F: FnMut(&mut dyn Read) -> FtpResult<bool>;
     fn put_file<S: AsRef<str>, R: Read>(&mut self, filename: S, r: &mut R) -> FtpResult<u64>;
     fn login<S: AsRef<str>>(&mut self, user: S, password: S) -> FtpResult<()>;
+    fn set_mode(&mut self, mode: Mode);
+    fn transfer_type(&mut self, file_type: FileType) -> FtpResult<()>;
+    /// Make the next transfer start `offset` bytes into the file (REST)
+    fn resume_transfer(&mut self, offset: usize) -> FtpResult<()>;
+    /// Send `command` as is and read the complete reply, which is an error
+    /// unless its status is one of `expected`
+    fn custom_command(&mut self, command: &str, expected: &[Status]) -> FtpResult<Response>;
+    /// Send a command typed by the user, whose reply is the result whatever
+    /// it is. It may change anything about the session, such as the TYPE.
+    fn quote(&mut self, command: &str) -> FtpResult<Response> {
+        self.custom_command(command, &[])
+    }
+
+    /// Set the modification time of a remote file (MFMT), `time` being UTC
+    fn mfmt<S: AsRef<str>>(&mut self, pathname: S, time: NaiveDateTime) -> FtpResult<()> {
+        let command = format!("MFMT {} {}", time.format("%Y%m%d%H%M%S"), pathname.as_ref());
+        self.custom_command(&command, &[Status::File]).map(|_| ())
+    }
+    fn site<S: AsRef<str>>(&mut self, command: S) -> FtpResult<Response> {
+        self.custom_command(&format!("SITE {}", command.as_ref()), SITE_OK)
+    }
+    /// The operating system the server reports through SYST
+    fn system(&mut self) -> FtpResult<String> {
+        let response = self.custom_command("SYST", &[Status::Name])?;
+        let text = String::from_utf8_lossy(&response.body);
+        Ok(text.get(4..).unwrap_or_default().trim_end().to_string())
+    }
+    /// The server's HELP text, in general or for one command
+    fn help(&mut self, command: Option<&str>) -> FtpResult<Response> {
+        let command = match command {
+            Some(command) => format!("HELP {}", command),
+            None => "HELP".to_string(),
+        };
+        self.custom_command(&command, &[Status::Help, Status::CommandOk])
+    }
+
+    /// Run `op` against the stream, giving implementations that know how to
+    /// recover from transient failures the chance to retry it
+    fn retrying<R, F>(&mut self, mut op: F) -> FtpResult<R>
+    where
+        Self: Sized,
+        F: FnMut(&mut Self) -> FtpResult<R>,
+    {
+        op(self)
+    }
 }
 
 impl IsFtpStream for FtpStream {
+    fn open(info: &ConnectionInfo) -> FtpResult<Self> {
+        connect(info)
+    }
     fn pwd(&mut self) -> FtpResult<String> {
         self.pwd()
     }
@@ -67,10 +241,19 @@ impl IsFtpStream for FtpStream {
     fn list(&mut self, pathname: Option<&str>) -> FtpResult<Vec<String>> {
         self.list(pathname)
     }
+    fn mlsd(&mut self, pathname: Option<&str>) -> FtpResult<Vec<String>> {
+        self.mlsd(pathname)
+    }
+    fn feat(&mut self) -> FtpResult<Features> {
+        self.feat()
+    }
 
     fn size<S: AsRef<str>>(&mut self, pathname: S) -> FtpResult<usize> {
         self.size(pathname)
     }
+    fn mdtm<S: AsRef<str>>(&mut self, pathname: S) -> FtpResult<NaiveDateTime> {
+        self.mdtm(pathname)
+    }
     fn append_file<R: Read>(&mut self, filename: &str, r: &mut R) -> FtpResult<u64> {
         self.append_file(filename, r)
     }
@@ -81,12 +264,29 @@ impl IsFtpStream for FtpStream {
     fn rmdir<S: AsRef<str>>(&mut self, pathname: S) -> FtpResult<()> {
         self.rmdir(pathname)
     }
+    fn rename<S: AsRef<str>>(&mut self, from_name: S, to_name: S) -> FtpResult<()> {
+        self.rename(from_name, to_name)
+    }
     fn retr<F, D>(&mut self, file_name: &str, reader: F) -> FtpResult<D>
     where
         F: FnMut(&mut dyn Read) -> FtpResult<D>,
     {
         self.retr(file_name, reader)
     }
+    fn retr_until<F>(&mut self, file_name: &str, mut reader: F) -> FtpResult<()>
+    where
+        F: FnMut(&mut dyn Read) -> FtpResult<bool>,
+    {
+        let mut stream = self.retr_as_stream(file_name)?;
+        match reader(&mut stream) {
+            Ok(true) => self.finalize_retr_stream(stream),
+            Ok(false) => self.abort(stream),
+            Err(e) => {
+                let _ = self.abort(stream);
+                Err(e)
+            }
+        }
+    }
     fn put_file<S: AsRef<str>, R: Read>(&mut self, filename: S, r: &mut R) -> FtpResult<u64> {
         self.put_file(filename, r)
     }
@@ -94,9 +294,24 @@ impl IsFtpStream for FtpStream {
     fn login<S: AsRef<str>>(&mut self, user: S, password: S) -> FtpResult<()> {
         self.login(user, password)
     }
+    fn custom_command(&mut self, command: &str, expected: &[Status]) -> FtpResult<Response> {
+        self.custom_command(command, expected)
+    }
+    fn set_mode(&mut self, mode: Mode) {
+        self.set_mode(mode)
+    }
+    fn transfer_type(&mut self, file_type: FileType) -> FtpResult<()> {
+        self.transfer_type(file_type)
+    }
+    fn resume_transfer(&mut self, offset: usize) -> FtpResult<()> {
+        self.resume_transfer(offset)
+    }
 }
 
 impl IsFtpStream for NativeTlsFtpStream {
+    fn open(info: &ConnectionInfo) -> FtpResult<Self> {
+        connect_tls(info)
+    }
     fn pwd(&mut self) -> FtpResult<String> {
         self.pwd()
     }
@@ -118,9 +333,18 @@ impl IsFtpStream for NativeTlsFtpStream {
     fn list(&mut self, pathname: Option<&str>) -> FtpResult<Vec<String>> {
         self.list(pathname)
     }
+    fn mlsd(&mut self, pathname: Option<&str>) -> FtpResult<Vec<String>> {
+        self.mlsd(pathname)
+    }
+    fn feat(&mut self) -> FtpResult<Features> {
+        self.feat()
+    }
     fn size<S: AsRef<str>>(&mut self, pathname: S) -> FtpResult<usize> {
         self.size(pathname)
     }
+    fn mdtm<S: AsRef<str>>(&mut self, pathname: S) -> FtpResult<NaiveDateTime> {
+        self.mdtm(pathname)
+    }
     fn append_file<R: Read>(&mut self, filename: &str, r: &mut R) -> FtpResult<u64> {
         self.append_file(filename, r)
     }
@@ -131,12 +355,29 @@ impl IsFtpStream for NativeTlsFtpStream {
     fn rmdir<S: AsRef<str>>(&mut self, pathname: S) -> FtpResult<()> {
         self.rmdir(pathname)
     }
+    fn rename<S: AsRef<str>>(&mut self, from_name: S, to_name: S) -> FtpResult<()> {
+        self.rename(from_name, to_name)
+    }
     fn retr<F, D>(&mut self, file_name: &str, reader: F) -> FtpResult<D>
     where
         F: FnMut(&mut dyn Read) -> FtpResult<D>,
     {
         self.retr(file_name, reader)
     }
+    fn retr_until<F>(&mut self, file_name: &str, mut reader: F) -> FtpResult<()>
+    where
+        F: FnMut(&mut dyn Read) -> FtpResult<bool>,
+    {
+        let mut stream = self.retr_as_stream(file_name)?;
+        match reader(&mut stream) {
+            Ok(true) => self.finalize_retr_stream(stream),
+            Ok(false) => self.abort(stream),
+            Err(e) => {
+                let _ = self.abort(stream);
+                Err(e)
+            }
+        }
+    }
 
     fn put_file<S: AsRef<str>, R: Read>(&mut self, filename: S, r: &mut R) -> FtpResult<u64> {
         self.put_file(filename, r)
@@ -145,4 +386,16 @@ impl IsFtpStream for NativeTlsFtpStream {
     fn login<S: AsRef<str>>(&mut self, user: S, password: S) -> FtpResult<()> {
         self.login(user, password)
     }
+    fn custom_command(&mut self, command: &str, expected: &[Status]) -> FtpResult<Response> {
+        self.custom_command(command, expected)
+    }
+    fn set_mode(&mut self, mode: Mode) {
+        self.set_mode(mode)
+    }
+    fn transfer_type(&mut self, file_type: FileType) -> FtpResult<()> {
+        self.transfer_type(file_type)
+    }
+    fn resume_transfer(&mut self, offset: usize) -> FtpResult<()> {
+        self.resume_transfer(offset)
+    }
 }
@@ -1,8 +1,21 @@
-use crate::ftp_handler::client::IsFtpStream;
+use crate::ftp_handler::checksum::{self, Algorithm};
+use crate::ftp_handler::client::{self, IsFtpStream};
+use crate::ftp_handler::conflict::{self, ConflictPolicy};
+use crate::ftp_handler::filter::{Filter, FindAction};
+use crate::ftp_handler::listing::{self, Entry, EntryKind, ListOptions};
+use crate::ftp_handler::retry::{self, Local};
+use crate::ftp_handler::settings::Settings;
+use crate::ftp_handler::trace;
+use crate::ftp_handler::transfer::{self, ToNetwork, TransferMode};
 use ansi_term::Color;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use regex::Regex;
 use std::fs::File;
 use std::io::prelude::*;
-use suppaftp::FtpError;
+use std::io::SeekFrom;
+use std::path::PathBuf;
+use suppaftp::types::{FileType, Response};
+use suppaftp::{FtpError, FtpResult, Status};
 
 pub fn pwd<T: IsFtpStream>(fs: &mut T) {
     match fs.pwd() {
@@ -33,6 +46,81 @@ pub fn lpwd() {
     };
 }
 
+pub fn lcd(settings: &mut Settings, local_dir: Option<&str>) {
+    let target = match local_dir {
+        Some("-") => match &settings.previous_local_dir {
+            Some(dir) => dir.clone(),
+            None => {
+                println!("{}", Color::Red.paint("[-] No previous local directory"));
+                return;
+            }
+        },
+        Some(dir) => expand_home(dir),
+        None => expand_home("~"),
+    };
+    let previous = std::env::current_dir().ok();
+    match std::env::set_current_dir(&target) {
+        Ok(()) => {
+            settings.previous_local_dir = previous;
+            lpwd();
+        }
+        Err(e) => {
+            println!("{}", Color::Red.paint(format!("[-] {}: {}", target.display(), e)));
+        }
+    };
+}
+
+pub fn lls(local_dir: &str, opts: &ListOptions) {
+    match listing::list_local(&expand_home(local_dir)) {
+        Ok(entries) => {
+            println!(
+                "Listing local directory: {}",
+                Color::Blue.bold().paint(local_dir)
+            );
+            let entries = listing::arrange(entries, opts);
+            for line in listing::render(&entries, opts) {
+                println!("{}", line);
+            }
+        }
+        Err(e) => {
+            println!("{}", Color::Red.paint(format!("[-] {}: {}", local_dir, e)));
+        }
+    };
+}
+
+pub fn lmkdir(local_dir: &str) {
+    match std::fs::create_dir(expand_home(local_dir)) {
+        Ok(()) => {
+            println!("{}", Color::Green.paint("[+] Success"));
+        }
+        Err(e) => {
+            println!("{}", Color::Red.paint(format!("[-] {}: {}", local_dir, e)));
+        }
+    };
+}
+
+pub fn lrm(local_file: &str) {
+    match std::fs::remove_file(expand_home(local_file)) {
+        Ok(()) => {
+            println!("{}", Color::Green.paint("[+] Success"));
+        }
+        Err(e) => {
+            println!("{}", Color::Red.paint(format!("[-] {}: {}", local_file, e)));
+        }
+    };
+}
+
+/// Expand a leading `~` to the home directory, as a shell would
+fn expand_home(path: &str) -> PathBuf {
+    let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"));
+    match (path.strip_prefix('~'), home) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+            PathBuf::from(home).join(rest.trim_start_matches('/'))
+        }
+        _ => PathBuf::from(path),
+    }
+}
+
 pub fn cwd<T: IsFtpStream>(fs: &mut T, remote_dir: &str) {
     match fs.cwd(remote_dir) {
         Ok(()) => {
@@ -57,15 +145,35 @@ pub fn cdup<T: IsFtpStream>(fs: &mut T) {
     };
 }
 
-pub fn ls<T: IsFtpStream>(fs: &mut T, remote_dir: &str) {
-    match fs.list(Some(remote_dir)) {
-        Ok(s) => {
+pub fn ls<T: IsFtpStream>(fs: &mut T, remote_dir: &str, opts: &ListOptions) {
+    match fs.retrying(|fs| listing::list(fs, remote_dir, opts.all)) {
+        Ok(entries) => {
             println!(
                 "Listing directory: {}",
                 Color::Blue.bold().paint(remote_dir)
             );
-            for i in s {
-                println!("{}", i);
+            let entries = listing::arrange(entries, opts);
+            for line in listing::render(&entries, opts) {
+                println!("{}", line);
+            }
+        }
+        Err(e) => {
+            print_error(e);
+        }
+    };
+}
+
+pub fn tree<T: IsFtpStream>(fs: &mut T, remote_dir: &str, max_depth: Option<usize>, dirs_only: bool) {
+    println!("{}", Color::Blue.bold().paint(remote_dir));
+    let mut counts = (0, 0);
+    match tree_level(fs, remote_dir, "", 1, max_depth, dirs_only, &mut counts) {
+        Ok(()) => {
+            println!();
+            let dirs = plural(counts.0, "directory", "directories");
+            if dirs_only {
+                println!("{}", dirs);
+            } else {
+                println!("{}, {}", dirs, plural(counts.1, "file", "files"));
             }
         }
         Err(e) => {
@@ -74,6 +182,197 @@ pub fn ls<T: IsFtpStream>(fs: &mut T, remote_dir: &str) {
     };
 }
 
+fn tree_level<T: IsFtpStream>(
+    fs: &mut T,
+    dir: &str,
+    prefix: &str,
+    depth: usize,
+    max_depth: Option<usize>,
+    dirs_only: bool,
+    counts: &mut (usize, usize),
+) -> FtpResult<()> {
+    let opts = ListOptions::default();
+    let mut entries = listing::arrange(fs.retrying(|fs| listing::list(fs, dir, false))?, &opts);
+    if dirs_only {
+        entries.retain(|e| e.is_dir());
+    }
+
+    for (i, entry) in entries.iter().enumerate() {
+        let last = i + 1 == entries.len();
+        let mut line = format!(
+            "{}{}{}",
+            prefix,
+            if last { "└── " } else { "├── " },
+            listing::paint_name(entry)
+        );
+        if let Some(target) = &entry.target {
+            line.push_str(&format!(" -> {}", target));
+        }
+        println!("{}", line);
+
+        if !entry.is_dir() {
+            counts.1 += 1;
+            continue;
+        }
+        counts.0 += 1;
+        if max_depth.is_none_or(|max| depth < max) {
+            let child_prefix = format!("{}{}", prefix, if last { "    " } else { "│   " });
+            let path = listing::join(dir, &entry.name);
+            match tree_level(fs, &path, &child_prefix, depth + 1, max_depth, dirs_only, counts) {
+                // An unreadable directory should not end the whole walk
+                Err(FtpError::UnexpectedResponse(r)) => println!(
+                    "{}└── {}",
+                    child_prefix,
+                    Color::Red.paint(format!("[error opening dir: {}]", r.status.desc()))
+                ),
+                result => result?,
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn du<T: IsFtpStream>(
+    fs: &mut T,
+    remote_dir: &str,
+    summarize: bool,
+    human: bool,
+    max_depth: Option<usize>,
+) {
+    // -s is --max-depth 0
+    let max_depth = if summarize { Some(0) } else { max_depth };
+    if let Err(e) = du_level(fs, remote_dir, 0, human, max_depth) {
+        print_error(e);
+    }
+}
+
+/// Total size of everything below `dir`, printing the totals of directories
+/// down to `max_depth` after their contents, as `du` does
+fn du_level<T: IsFtpStream>(
+    fs: &mut T,
+    dir: &str,
+    depth: usize,
+    human: bool,
+    max_depth: Option<usize>,
+) -> FtpResult<u64> {
+    let mut total = 0;
+    for entry in fs.retrying(|fs| listing::list(fs, dir, false))? {
+        let path = listing::join(dir, &entry.name);
+        total += match (entry.kind, entry.size) {
+            (EntryKind::Directory, _) => match du_level(fs, &path, depth + 1, human, max_depth) {
+                Ok(size) => size,
+                // An unreadable directory should not end the whole walk
+                Err(FtpError::UnexpectedResponse(r)) => {
+                    println!(
+                        "{}",
+                        Color::Red.paint(format!("[-] cannot read {}: {}", path, r.status.desc()))
+                    );
+                    0
+                }
+                Err(e) => return Err(e),
+            },
+            (EntryKind::File, None) => fs.size(&path).unwrap_or(0) as u64,
+            (_, size) => size.unwrap_or(0),
+        };
+    }
+
+    if max_depth.is_none_or(|max| depth <= max) {
+        let size = if human {
+            listing::human_size(total)
+        } else {
+            total.to_string()
+        };
+        println!("{}\t{}", Color::Yellow.paint(size), dir);
+    }
+    Ok(total)
+}
+
+/// Search below `remote_dir`, then act on the matches. Deleting them asks
+/// first unless `force` is given.
+pub fn find<T: IsFtpStream>(
+    fs: &mut T,
+    remote_dir: &str,
+    filter: &Filter,
+    action: FindAction,
+    force: bool,
+    settings: &Settings,
+) {
+    let mut matches = Vec::new();
+    if let Err(e) = find_level(fs, remote_dir, filter, &mut matches) {
+        print_error(e);
+        return;
+    }
+
+    match action {
+        FindAction::Print => {}
+        FindAction::Get => {
+            // Files from different directories may share a name
+            let mut used = std::collections::HashSet::new();
+            for (path, entry) in matches.iter().filter(|(_, e)| e.kind == EntryKind::File) {
+                let Some(name) = safe_local_name(&entry.name) else {
+                    println!("{}", Color::Yellow.paint(format!("[*] Skipping {}, its name is not usable locally", path)));
+                    continue;
+                };
+                let name = if used.contains(&name) {
+                    conflict::numbered_name(&name, |n| used.contains(n))
+                } else {
+                    name
+                };
+                println!("Retrieving {}", Color::Yellow.dimmed().paint(path.as_str()));
+                get(fs, path, Some(&name), false, settings.conflict, settings);
+                used.insert(name);
+            }
+        }
+        FindAction::Delete if matches.is_empty() => {}
+        FindAction::Delete => {
+            let question = format!("Delete {}?", plural(matches.len(), "match", "matches"));
+            if !force && !confirm(&question) {
+                return;
+            }
+            // Matches are in walk order, so going backwards empties
+            // directories before they are removed
+            for (path, entry) in matches.iter().rev() {
+                if entry.is_dir() {
+                    rmdir(fs, path);
+                } else {
+                    rm(fs, path);
+                }
+            }
+        }
+    }
+}
+
+/// Print, and collect, every entry below `dir` that passes `filter`
+fn find_level<T: IsFtpStream>(
+    fs: &mut T,
+    dir: &str,
+    filter: &Filter,
+    matches: &mut Vec<(String, Entry)>,
+) -> FtpResult<()> {
+    for entry in fs.retrying(|fs| listing::list(fs, dir, false))? {
+        let path = listing::join(dir, &entry.name);
+        if filter.matches(&entry) {
+            if entry.is_dir() {
+                println!("{}", Color::Blue.bold().paint(path.as_str()));
+            } else {
+                println!("{}", path);
+            }
+            matches.push((path.clone(), entry.clone()));
+        }
+        if entry.is_dir() {
+            match find_level(fs, &path, filter, matches) {
+                // An unreadable directory should not end the whole walk
+                Err(FtpError::UnexpectedResponse(r)) => println!(
+                    "{}",
+                    Color::Red.paint(format!("[-] cannot read {}: {}", path, r.status.desc()))
+                ),
+                result => result?,
+            }
+        }
+    }
+    Ok(())
+}
+
 pub fn mkdir<T: IsFtpStream>(fs: &mut T, new_dir: &str) {
     match fs.mkdir(new_dir) {
         Ok(()) => {
@@ -86,7 +385,25 @@ pub fn mkdir<T: IsFtpStream>(fs: &mut T, new_dir: &str) {
 }
 
 pub fn append<T: IsFtpStream>(fs: &mut T, local_file: &mut File, remote_file: &str) {
-    match fs.append_file(remote_file, local_file) {
+    // Resuming by size only works if the bytes go over unchanged, and many
+    // servers refuse SIZE in ASCII mode
+    let start = fs
+        .transfer_type(FileType::Binary)
+        .and_then(|_| remote_size(fs, remote_file));
+    let mut first = true;
+    match fs.retrying(|fs| {
+        fs.transfer_type(FileType::Binary)?;
+        // A retry resumes after whatever the failed attempt already appended
+        let done = if std::mem::take(&mut first) {
+            0
+        } else {
+            appended(fs, remote_file, &start)?
+        };
+        local_file
+            .seek(SeekFrom::Start(done))
+            .map_err(retry::local_error)?;
+        fs.append_file(remote_file, &mut Local(local_file)).map(|n| n + done)
+    }) {
         Ok(s) => {
             println!("{}", Color::White.paint(format!("{}", s)))
         }
@@ -96,6 +413,37 @@ pub fn append<T: IsFtpStream>(fs: &mut T, local_file: &mut File, remote_file: &s
     };
 }
 
+/// Size of `remote_file`, 0 if it does not exist
+fn remote_size<T: IsFtpStream>(fs: &mut T, remote_file: &str) -> FtpResult<u64> {
+    match fs.size(remote_file) {
+        Ok(size) => Ok(size as u64),
+        Err(FtpError::UnexpectedResponse(r)) if r.status == Status::FileUnavailable => Ok(0),
+        Err(e) => Err(e),
+    }
+}
+
+/// How much of an interrupted append reached `remote_file`, which was
+/// `start` bytes long before. Resuming from a guess would leave the file
+/// with bytes missing or repeated, so if either size is unknown this is an
+/// error that ends the retries.
+fn appended<T: IsFtpStream>(fs: &mut T, remote_file: &str, start: &FtpResult<u64>) -> FtpResult<u64> {
+    let cannot_resume = |reason: String| {
+        FtpError::ConnectionError(retry::permanent(
+            std::io::ErrorKind::Other,
+            format!("cannot resume appending to {}: {}", remote_file, reason),
+        ))
+    };
+    let start = match start {
+        Ok(start) => *start,
+        Err(e) => return Err(cannot_resume(format!("its size before was unknown ({})", e))),
+    };
+    match fs.size(remote_file) {
+        Ok(size) if size as u64 >= start => Ok(size as u64 - start),
+        Ok(size) => Err(cannot_resume(format!("it shrank from {} to {} bytes", start, size))),
+        Err(e) => Err(cannot_resume(format!("its size is unknown ({})", e))),
+    }
+}
+
 pub fn rm<T: IsFtpStream>(fs: &mut T, remote_file: &str) {
     match fs.rm(remote_file) {
         Ok(()) => {
@@ -118,16 +466,130 @@ pub fn rmdir<T: IsFtpStream>(fs: &mut T, remote_dir: &str) {
     };
 }
 
+pub fn rename<T: IsFtpStream>(fs: &mut T, from: &str, to: &str) {
+    // Like mv, renaming onto an existing directory moves the file into it
+    let into_dir = to.ends_with('/')
+        || match is_remote_dir(fs, to) {
+            Ok(is_dir) => is_dir,
+            Err(e) => {
+                print_error(e);
+                return;
+            }
+        };
+    let to = if into_dir {
+        let name = from.trim_end_matches('/').rsplit('/').next().unwrap_or(from);
+        listing::join(to, name)
+    } else {
+        to.to_string()
+    };
+    match fs.rename(from, to.as_str()) {
+        Ok(()) => {
+            println!(
+                "Renamed {} to {}",
+                Color::Yellow.dimmed().paint(from),
+                Color::Yellow.dimmed().paint(to)
+            );
+        }
+        Err(e) => {
+            print_error(e);
+        }
+    };
+}
+
+/// Rename every entry of the current directory whose name matches `pattern`,
+/// showing the new names and asking before touching anything
+pub fn rename_matching<T: IsFtpStream>(fs: &mut T, pattern: &Regex, replacement: &str) {
+    let entries = match fs.retrying(|fs| listing::list(fs, ".", false)) {
+        Ok(entries) => entries,
+        Err(e) => {
+            print_error(e);
+            return;
+        }
+    };
+    let renames = entries
+        .iter()
+        .filter(|e| pattern.is_match(&e.name))
+        .map(|e| (e.name.as_str(), pattern.replace(&e.name, replacement).into_owned()))
+        .filter(|(from, to)| from != to)
+        .collect::<Vec<_>>();
+    if renames.is_empty() {
+        println!("{}", Color::Yellow.paint("[*] Nothing to rename"));
+        return;
+    }
+
+    for (from, to) in &renames {
+        println!("{} -> {}", from, Color::Yellow.dimmed().paint(to.as_str()));
+    }
+    let names = entries.iter().map(|e| e.name.as_str()).collect::<Vec<_>>();
+    if let Some(to) = rename_clash(&names, &renames) {
+        println!("{}", Color::Red.paint(format!("[-] More than one file would be named {}", to)));
+        return;
+    }
+    let Some(renames) = rename_order(&renames) else {
+        println!("{}", Color::Red.paint("[-] The new names go round in a circle, rename in two steps"));
+        return;
+    };
+    if !confirm(&format!("Rename {}?", plural(renames.len(), "entry", "entries"))) {
+        return;
+    }
+
+    for (done, (from, to)) in renames.iter().enumerate() {
+        if let Err(e) = fs.rename(*from, to.as_str()) {
+            print!("{}: ", from);
+            print_error(e);
+            // Later renames may take the name this one failed to free
+            println!(
+                "{}",
+                Color::Yellow.paint(format!("[*] Stopped after renaming {}", plural(done, "entry", "entries")))
+            );
+            return;
+        }
+    }
+}
+
+/// The first new name that two of `renames` share, or that belongs to one of
+/// the `existing` names that is not renamed away
+fn rename_clash<'r>(existing: &[&str], renames: &'r [(&str, String)]) -> Option<&'r str> {
+    let taken = |name: &str| {
+        renames.iter().filter(|(_, to)| to == name).count() > 1
+            || existing.iter().any(|e| *e == name && !renames.iter().any(|(from, _)| *from == name))
+    };
+    renames.iter().map(|(_, to)| to.as_str()).find(|to| taken(to))
+}
+
+/// Order renames so that every file is moved out of the way before another
+/// is given its name; `None` if they go round in a circle
+fn rename_order<'a>(renames: &[(&'a str, String)]) -> Option<Vec<(&'a str, String)>> {
+    let mut pending = renames.to_vec();
+    let mut ordered = Vec::with_capacity(pending.len());
+    while !pending.is_empty() {
+        let ready = pending
+            .iter()
+            .position(|(_, to)| !pending.iter().any(|(from, _)| from == to))?;
+        ordered.push(pending.remove(ready));
+    }
+    Some(ordered)
+}
+
+/// Whether `path` names a directory on the remote machine, found out by
+/// trying to change into it and back. Failing to get back is an error, as
+/// the working directory would be left changed.
+fn is_remote_dir<T: IsFtpStream>(fs: &mut T, path: &str) -> FtpResult<bool> {
+    let pwd = fs.pwd()?;
+    if fs.cwd(path).is_err() {
+        return Ok(false);
+    }
+    fs.cwd(pwd)?;
+    Ok(true)
+}
+
 pub fn size<T: IsFtpStream>(fs: &mut T, remote_file: &str) {
     match fs.size(remote_file) {
         Ok(s) => {
             println!(
-                "{}",
-                format!(
-                    "Size of {}: {}",
-                    Color::Yellow.dimmed().paint(remote_file),
-                    s
-                )
+                "Size of {}: {}",
+                Color::Yellow.dimmed().paint(remote_file),
+                s
             );
         }
         Err(e) => {
@@ -136,8 +598,37 @@ pub fn size<T: IsFtpStream>(fs: &mut T, remote_file: &str) {
     };
 }
 
-pub fn put<T: IsFtpStream>(fs: &mut T, local_file: &mut File, remote_file: &str) {
-    match fs.put_file(remote_file, local_file) {
+pub fn mdtm<T: IsFtpStream>(fs: &mut T, remote_file: &str) {
+    match fs.mdtm(remote_file) {
+        Ok(time) => {
+            println!(
+                "Modification time of {}: {}",
+                Color::Yellow.dimmed().paint(remote_file),
+                time.format("%Y-%m-%d %H:%M:%S UTC")
+            );
+        }
+        Err(e) => {
+            print_error(e);
+        }
+    };
+}
+
+pub fn put<T: IsFtpStream>(
+    fs: &mut T,
+    local_file: &str,
+    remote_file: &str,
+    policy: ConflictPolicy,
+    settings: &Settings,
+) {
+    let mut file = match File::open(local_file) {
+        Ok(file) => file,
+        Err(e) => {
+            println!("{}", Color::Red.paint(format!("[-] {}", e)));
+            return;
+        }
+    };
+    let Some(remote_file) = remote_target(fs, &file, remote_file, policy) else { return };
+    match upload(fs, &mut file, &remote_file, settings) {
         Ok(n) => {
             println!("Wrote {} bytes", n);
         }
@@ -147,29 +638,976 @@ pub fn put<T: IsFtpStream>(fs: &mut T, local_file: &mut File, remote_file: &str)
     };
 }
 
-pub fn get<T: IsFtpStream>(fs: &mut T, remote_file: &str, local_file: &mut File) {
-    match fs.retr(remote_file, |stream| {
-        let mut buf = Vec::new();
-        stream
-            .read_to_end(&mut buf)
-            .map_err(|e| FtpError::ConnectionError(e))?;
+/// Download `remote_file` to `local_file`, or under its own name into the
+/// working directory. With `make_parents` missing local directories are
+/// created on the way.
+pub fn get<T: IsFtpStream>(
+    fs: &mut T,
+    remote_file: &str,
+    local_file: Option<&str>,
+    make_parents: bool,
+    policy: ConflictPolicy,
+    settings: &Settings,
+) {
+    let Some(local_path) = local_path(remote_file, local_file) else {
+        println!(
+            "{}",
+            Color::Red.paint(format!("[-] {} has no usable local name, give one", remote_file))
+        );
+        return;
+    };
+    if let Some(parent) = local_path.parent().filter(|p| !p.as_os_str().is_empty() && !p.exists()) {
+        if !make_parents {
+            println!(
+                "{}",
+                Color::Red.paint(format!(
+                    "[-] Local directory {} does not exist, use get -p to create it",
+                    parent.display()
+                ))
+            );
+            return;
+        }
+        if let Err(e) = std::fs::create_dir_all(parent) {
+            println!("{}", Color::Red.paint(format!("[-] {}: {}", parent.display(), e)));
+            return;
+        }
+    }
+    let local_file = local_path.to_string_lossy();
+    let Some(local_file) = local_target(fs, remote_file, &local_file, policy) else { return };
+
+    // Whatever is at `local_file` stays untouched until the download is
+    // complete and has replaced it
+    let part_path = local_part_path(std::path::Path::new(&local_file));
+    let mut file = match create_local(&part_path) {
+        Ok(file) => file,
+        Err(e) => {
+            println!("{}", Color::Red.paint(format!("[-] {}: {}", part_path.display(), e)));
+            return;
+        }
+    };
+    let downloaded = download(fs, remote_file, &mut file, settings);
+    drop(file);
+    match downloaded {
+        Ok(()) => {
+            if let Err(e) = std::fs::rename(&part_path, &local_file) {
+                println!("{}", Color::Red.paint(format!("[-] {}: {}", local_file, e)));
+                let _ = std::fs::remove_file(&part_path);
+            }
+        }
+        Err(e) => {
+            let mismatch = matches!(&e, FtpError::ConnectionError(e) if checksum::is_mismatch(e));
+            print_error(e);
+            let _ = std::fs::remove_file(&part_path);
+            if mismatch {
+                println!("{}", Color::Yellow.paint(format!("[*] Discarded the download, {} was not written", local_file)));
+            }
+        }
+    };
+}
+
+/// Where a download to `local_file` is written until it is complete: a
+/// hidden file next to it, `dir/.name.rsftp-part`
+fn local_part_path(local_file: &std::path::Path) -> PathBuf {
+    let name = local_file.file_name().unwrap_or_default().to_string_lossy();
+    local_file.with_file_name(format!(".{}.rsftp-part", name))
+}
+
+/// A name from the server made fit to save a file under: its last path
+/// component, which must not be empty or lead out of the directory
+fn safe_local_name(name: &str) -> Option<String> {
+    let name = name
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or(name)
+        .chars()
+        .filter(|c| !c.is_control())
+        .collect::<String>();
+    match name.as_str() {
+        "" | "." | ".." => None,
+        _ => Some(name),
+    }
+}
+
+/// Where `get` saves `remote_file`: `local_file` if given, inside it if that
+/// is a directory, and otherwise the working directory. Only the last
+/// component of the remote path is used as the name, `None` if that is not
+/// usable. `local_file` is taken as it is, so callers other than the user
+/// pass it through `safe_local_name` first.
+fn local_path(remote_file: &str, local_file: Option<&str>) -> Option<PathBuf> {
+    let name = || safe_local_name(remote_file.trim_end_matches('/'));
+    match local_file {
+        None => name().map(PathBuf::from),
+        Some(local_file) => {
+            let path = expand_home(local_file);
+            if local_file.ends_with('/') || path.is_dir() {
+                name().map(|name| path.join(name))
+            } else {
+                Some(path)
+            }
+        }
+    }
+}
+
+/// The name a download of `remote_file` should be saved under, given that
+/// `local_file` may already exist; `None` to leave it alone
+fn local_target<T: IsFtpStream>(
+    fs: &mut T,
+    remote_file: &str,
+    local_file: &str,
+    policy: ConflictPolicy,
+) -> Option<String> {
+    if !std::path::Path::new(local_file).exists() {
+        return Some(local_file.to_string());
+    }
+    let remote_is_newer = || {
+        let remote = fs.mdtm(remote_file).ok()?;
+        let local: DateTime<Utc> = std::fs::metadata(local_file).and_then(|m| m.modified()).ok()?.into();
+        Some(remote.and_utc().timestamp() > local.timestamp())
+    };
+    match resolve_conflict(local_file, policy, remote_is_newer)? {
+        Resolution::Overwrite => Some(local_file.to_string()),
+        Resolution::Rename => {
+            let renamed = conflict::numbered_name(local_file, |name| std::path::Path::new(name).exists());
+            println!("Saving as {}", Color::Yellow.dimmed().paint(renamed.as_str()));
+            Some(renamed)
+        }
+    }
+}
+
+/// The name an upload of `local_file` should be stored under, given that
+/// `remote_file` may already exist; `None` to leave it alone
+fn remote_target<T: IsFtpStream>(
+    fs: &mut T,
+    local_file: &File,
+    remote_file: &str,
+    policy: ConflictPolicy,
+) -> Option<String> {
+    if !remote_exists(fs, remote_file) {
+        return Some(remote_file.to_string());
+    }
+    let local_is_newer = || {
+        let local: DateTime<Utc> = local_file.metadata().and_then(|m| m.modified()).ok()?.into();
+        let remote = fs.mdtm(remote_file).ok()?;
+        Some(local.timestamp() > remote.and_utc().timestamp())
+    };
+    match resolve_conflict(remote_file, policy, local_is_newer)? {
+        Resolution::Overwrite => Some(remote_file.to_string()),
+        Resolution::Rename => {
+            let renamed = conflict::numbered_name(remote_file, |name| remote_exists(fs, name));
+            println!("Storing as {}", Color::Yellow.dimmed().paint(renamed.as_str()));
+            Some(renamed)
+        }
+    }
+}
+
+/// Whether a file is in the way on the server. SIZE answers that in one
+/// round trip, but only a 550 from it means there is no such file: servers
+/// without it, or that refuse it for another reason, are asked for a
+/// listing instead.
+fn remote_exists<T: IsFtpStream>(fs: &mut T, remote_file: &str) -> bool {
+    // Many servers refuse SIZE in ASCII mode
+    let _ = fs.transfer_type(FileType::Binary);
+    match fs.size(remote_file) {
+        Ok(_) => true,
+        Err(FtpError::UnexpectedResponse(r)) if r.status == Status::FileUnavailable => false,
+        Err(_) => fs.list(Some(remote_file)).is_ok_and(|lines| !lines.is_empty()),
+    }
+}
+
+enum Resolution {
+    Overwrite,
+    Rename,
+}
+
+/// Apply `policy` to an existing `target`, asking the user if it says so or
+/// if it is `newer` and the modification times cannot be compared. `None`
+/// means the transfer is skipped.
+fn resolve_conflict<F>(target: &str, policy: ConflictPolicy, source_is_newer: F) -> Option<Resolution>
+where
+    F: FnOnce() -> Option<bool>,
+{
+    match policy {
+        ConflictPolicy::Overwrite => Some(Resolution::Overwrite),
+        ConflictPolicy::Rename => Some(Resolution::Rename),
+        ConflictPolicy::Skip => {
+            println!("{}", Color::Yellow.paint(format!("[*] {} exists, skipped", target)));
+            None
+        }
+        ConflictPolicy::Newer => match source_is_newer() {
+            Some(true) => Some(Resolution::Overwrite),
+            Some(false) => {
+                println!("{}", Color::Yellow.paint(format!("[*] {} is up to date, skipped", target)));
+                None
+            }
+            None => {
+                println!(
+                    "{}",
+                    Color::Yellow.paint(format!("[*] Cannot tell whether {} is older", target))
+                );
+                ask_conflict(target)
+            }
+        },
+        ConflictPolicy::Ask => ask_conflict(target),
+    }
+}
+
+fn ask_conflict(target: &str) -> Option<Resolution> {
+    print!(
+        "{} ",
+        Color::White.bold().paint(format!("{} exists. [o]verwrite, [s]kip or [r]ename? [s]", target))
+    );
+    let _ = std::io::stdout().flush();
+    let mut answer = String::new();
+    let _ = std::io::stdin().read_line(&mut answer);
+    match answer.trim() {
+        "o" | "O" | "overwrite" => Some(Resolution::Overwrite),
+        "r" | "R" | "rename" => Some(Resolution::Rename),
+        _ => {
+            println!("{}", Color::Yellow.paint(format!("[*] {} skipped", target)));
+            None
+        }
+    }
+}
+
+/// Store `local_file` as `remote_file`. With atomic uploads set it is
+/// written under a temporary name and only renamed into place once it is
+/// complete, so that nobody picks up half a file.
+fn upload<T: IsFtpStream>(
+    fs: &mut T,
+    local_file: &mut File,
+    remote_file: &str,
+    settings: &Settings,
+) -> FtpResult<u64> {
+    if !settings.atomic {
+        return store(fs, local_file, remote_file, remote_file, settings);
+    }
+    let part_file = hidden_sibling(remote_file, "rsftp-part");
+    let n = match store(fs, local_file, remote_file, &part_file, settings) {
+        Ok(n) => n,
+        Err(e) => {
+            let _ = fs.rm(&part_file);
+            return Err(e);
+        }
+    };
+    move_into_place(fs, &part_file, remote_file)?;
+    Ok(n)
+}
+
+/// A hidden file next to `remote_file` for it to be written to or moved
+/// aside as, `dir/.name.SUFFIX`
+fn hidden_sibling(remote_file: &str, suffix: &str) -> String {
+    match remote_file.rsplit_once('/') {
+        Some((dir, name)) => format!("{}/.{}.{}", dir, name, suffix),
+        None => format!(".{}.{}", remote_file, suffix),
+    }
+}
+
+/// Rename a finished upload over `remote_file`. Some servers refuse to
+/// rename onto an existing file; that is then moved aside first and put back
+/// if the upload still cannot take its place. The upload is only removed on
+/// failure while the original is still where it was.
+fn move_into_place<T: IsFtpStream>(fs: &mut T, part_file: &str, remote_file: &str) -> FtpResult<()> {
+    let refused = match fs.rename(part_file, remote_file) {
+        Ok(()) => return Ok(()),
+        Err(e) => e,
+    };
+    let backup_file = hidden_sibling(remote_file, "rsftp-old");
+    let moved_aside = matches!(refused, FtpError::UnexpectedResponse(_))
+        && remote_exists(fs, remote_file)
+        && fs.rename(remote_file, backup_file.as_str()).is_ok();
+    if !moved_aside {
+        let _ = fs.rm(part_file);
+        return Err(refused);
+    }
+
+    if let Err(e) = fs.rename(part_file, remote_file) {
+        if fs.rename(backup_file.as_str(), remote_file).is_ok() {
+            let _ = fs.rm(part_file);
+        } else {
+            println!(
+                "{}",
+                Color::Yellow.paint(format!(
+                    "[*] The old {} is kept as {} and the upload as {}",
+                    remote_file, backup_file, part_file
+                ))
+            );
+        }
+        return Err(e);
+    }
+    if let Err(e) = fs.rm(&backup_file) {
+        println!(
+            "{}",
+            Color::Yellow.paint(format!("[*] Could not remove the old file {}: {}", backup_file, e))
+        );
+    }
+    Ok(())
+}
+
+/// Write `local_file` to the server as `stored_as`, checking it and then
+/// carrying its modification time and permissions over as far as the
+/// settings ask for and the server allows. The transfer type is chosen by
+/// `remote_file`, the name it ends up with.
+fn store<T: IsFtpStream>(
+    fs: &mut T,
+    local_file: &mut File,
+    remote_file: &str,
+    stored_as: &str,
+    settings: &Settings,
+) -> FtpResult<u64> {
+    let file_type = settings.transfer_mode.file_type(remote_file);
+    let n = fs.retrying(|fs| {
+        local_file.rewind().map_err(retry::local_error)?;
+        fs.transfer_type(file_type.clone())?;
+        let mut local_file = Local(&mut *local_file);
+        match file_type {
+            FileType::Binary => fs.put_file(stored_as, &mut local_file),
+            _ => fs.put_file(stored_as, &mut ToNetwork::new(&mut local_file)),
+        }
+    })?;
+    if settings.verify {
+        verify(fs, local_file, stored_as, &file_type)?;
+    }
+
+    if settings.preserve_time && client::has_feature(fs, "MFMT") {
+        let modified = local_file.metadata().and_then(|m| m.modified());
+        if let Ok(modified) = modified {
+            let modified: DateTime<Utc> = modified.into();
+            if let Err(e) = fs.mfmt(stored_as, modified.naive_utc()) {
+                println!(
+                    "{}",
+                    Color::Yellow.paint(format!("[*] Could not set the modification time: {}", e))
+                );
+            }
+        }
+    }
+    if settings.preserve_mode {
+        if let Some(mode) = local_mode(local_file) {
+            if let Err(e) = fs.site(format!("CHMOD {:o} {}", mode, stored_as)) {
+                println!(
+                    "{}",
+                    Color::Yellow.paint(format!("[*] Could not set the permissions: {}", e))
+                );
+            }
+        }
+    }
+    Ok(n)
+}
+
+/// Fetch `remote_file` into `local_file`, replacing whatever it held
+fn download<T: IsFtpStream>(
+    fs: &mut T,
+    remote_file: &str,
+    local_file: &mut File,
+    settings: &Settings,
+) -> FtpResult<()> {
+    let file_type = settings.transfer_mode.file_type(remote_file);
+    fs.retrying(|fs| {
         local_file
-            .write(String::from_utf8(buf).unwrap().as_bytes())
-            .map_err(|e| FtpError::ConnectionError(e))?;
-        Ok(())
-    }) {
+            .rewind()
+            .and_then(|_| local_file.set_len(0))
+            .map_err(retry::local_error)?;
+        fs.transfer_type(file_type.clone())?;
+        fs.retr(remote_file, |stream| {
+            let mut local_file = Local(&mut *local_file);
+            match file_type {
+                FileType::Binary => std::io::copy(stream, &mut local_file),
+                _ => transfer::copy_to_local(stream, &mut local_file),
+            }
+            .map_err(FtpError::ConnectionError)
+        })
+    })?;
+    if settings.verify {
+        verify(fs, local_file, remote_file, &file_type)?;
+    }
+
+    // Servers without MDTM simply leave the file with the current time
+    if settings.preserve_time {
+        if let Ok(modified) = fs.mdtm(remote_file) {
+            let _ = local_file.set_modified(modified.and_utc().into());
+        }
+    }
+    Ok(())
+}
+
+/// Compare the checksum of a transferred file on both ends, failing if they
+/// differ. Servers that cannot compute one only earn a warning.
+fn verify<T: IsFtpStream>(
+    fs: &mut T,
+    local_file: &mut File,
+    remote_file: &str,
+    file_type: &FileType,
+) -> FtpResult<()> {
+    if *file_type != FileType::Binary {
+        println!("{}", Color::Yellow.paint("[*] Not verified: ASCII transfers change line endings"));
+        return Ok(());
+    }
+    let Some(remote) = checksum::remote(fs, remote_file, None)? else {
+        println!("{}", Color::Yellow.paint("[*] Not verified: the server cannot compute checksums"));
+        return Ok(());
+    };
+    let local = local_file
+        .rewind()
+        .and_then(|_| checksum::local(local_file, remote.algorithm))
+        .map_err(retry::local_error)?;
+    if !local.matches(&remote) {
+        let file = remote_file.to_string();
+        return Err(checksum::Mismatch { file, local, remote }.into_error());
+    }
+    println!("{}", Color::Green.paint(format!("[+] {} checksum verified", remote.algorithm.name())));
+    Ok(())
+}
+
+/// Print the server's checksum of `remote_file`, and compare it with
+/// `local_file` if one is given
+pub fn hash<T: IsFtpStream>(
+    fs: &mut T,
+    remote_file: &str,
+    local_file: Option<&str>,
+    algorithm: Option<Algorithm>,
+) {
+    let remote = match checksum::remote(fs, remote_file, algorithm) {
+        Ok(Some(remote)) => remote,
+        Ok(None) => {
+            let what = algorithm.map_or("checksums", |a| a.name());
+            println!("{}", Color::Red.paint(format!("[-] The server cannot compute {}", what)));
+            return;
+        }
+        Err(e) => {
+            print_error(e);
+            return;
+        }
+    };
+    println!(
+        "{} {} {}",
+        Color::White.dimmed().paint(remote.algorithm.name()),
+        remote.value,
+        Color::Yellow.dimmed().paint(remote_file)
+    );
+
+    let Some(local_file) = local_file else { return };
+    let local = File::open(local_file).and_then(|mut file| checksum::local(&mut file, remote.algorithm));
+    match local {
+        Ok(local) if local.matches(&remote) => {
+            println!("{}", Color::Green.paint(format!("[+] {} matches", local_file)));
+        }
+        Ok(local) => {
+            println!(
+                "{}",
+                Color::Red.paint(format!("[-] {} differs: {} {}", local_file, remote.algorithm.name(), local.value))
+            );
+        }
+        Err(e) => {
+            println!("{}", Color::Red.paint(format!("[-] {}: {}", local_file, e)));
+        }
+    };
+}
+
+pub fn atomic_uploads(settings: &mut Settings, enable: Option<bool>) {
+    settings.atomic = enable.unwrap_or(!settings.atomic);
+    println!(
+        "Atomic uploads {}",
+        if settings.atomic { Color::Green.paint("on") } else { Color::Red.paint("off") }
+    );
+}
+
+pub fn verify_transfers(settings: &mut Settings, enable: Option<bool>) {
+    settings.verify = enable.unwrap_or(!settings.verify);
+    println!(
+        "Verifying transfers {}",
+        if settings.verify { Color::Green.paint("on") } else { Color::Red.paint("off") }
+    );
+}
+
+/// A remote file being edited by way of a temporary copy: fetched with
+/// `edit_fetch`, opened with `edit_run` and put back with `edit_store`. The
+/// session need not be held while the editor is open.
+pub struct Edit {
+    remote_file: String,
+    temp_path: PathBuf,
+    original: Vec<u8>,
+    before: (Option<NaiveDateTime>, Option<usize>),
+}
+
+impl Edit {
+    /// Give up on the edit, leaving the temporary copy for the user
+    pub fn keep(self) {
+        println!(
+            "{}",
+            Color::Yellow.paint(format!("[*] Your version is kept in {}", self.temp_path.display()))
+        );
+    }
+
+    fn discard(self) {
+        let _ = std::fs::remove_file(&self.temp_path);
+    }
+}
+
+/// Download a remote file into a private temporary copy for editing
+pub fn edit_fetch<T: IsFtpStream>(fs: &mut T, remote_file: &str, settings: &Settings) -> Option<Edit> {
+    let name = remote_file.rsplit('/').next().unwrap_or(remote_file);
+    let (temp_path, mut temp_file) = match private_temp_file(name) {
+        Ok(created) => created,
+        Err(e) => {
+            println!("{}", Color::Red.paint(format!("[-] Could not create a temporary file: {}", e)));
+            return None;
+        }
+    };
+
+    let before = remote_stamp(fs, remote_file);
+    if let Err(e) = download(fs, remote_file, &mut temp_file, settings) {
+        print_error(e);
+        let _ = std::fs::remove_file(&temp_path);
+        return None;
+    }
+    Some(Edit {
+        remote_file: remote_file.to_string(),
+        original: std::fs::read(&temp_path).unwrap_or_default(),
+        temp_path,
+        before,
+    })
+}
+
+/// Open the copy in `$VISUAL`/`$EDITOR`, `vi` by default. The path is passed
+/// as an argument of its own, never through a shell, as the name comes from
+/// the server. Hands the edit back if it is to be stored.
+pub fn edit_run(edit: Edit) -> Option<Edit> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| String::from("vi"));
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or("vi");
+    let status = std::process::Command::new(program)
+        .args(words)
+        .arg(&edit.temp_path)
+        .status();
+    match status {
+        Ok(status) if status.success() => Some(edit),
+        Ok(status) => {
+            println!("{}", Color::Yellow.paint(format!("[*] {} exited with {}, not uploading", editor, status)));
+            edit.discard();
+            None
+        }
+        Err(e) => {
+            println!("{}", Color::Red.paint(format!("[-] {}: {}", editor, e)));
+            edit.discard();
+            None
+        }
+    }
+}
+
+/// Upload the copy again if it was changed. Unless `force` is given, nothing
+/// is uploaded if the remote file is known to have changed in the meantime.
+pub fn edit_store<T: IsFtpStream>(fs: &mut T, edit: Edit, force: bool, settings: &Settings) {
+    if std::fs::read(&edit.temp_path).unwrap_or_default() == edit.original {
+        println!("{}", Color::Yellow.paint("[*] No changes, nothing uploaded"));
+        edit.discard();
+        return;
+    }
+    if !force && stamp_changed(edit.before, remote_stamp(fs, &edit.remote_file)) {
+        println!(
+            "{}",
+            Color::Red.paint(format!(
+                "[-] {} changed on the server while it was being edited. Use edit -f to overwrite it anyway",
+                edit.remote_file
+            ))
+        );
+        edit.keep();
+        return;
+    }
+
+    // The editor may have replaced the file rather than written to it
+    let uploaded = File::open(&edit.temp_path)
+        .map_err(retry::local_error)
+        .and_then(|mut file| upload(fs, &mut file, &edit.remote_file, settings));
+    match uploaded {
+        Ok(n) => {
+            println!("Wrote {} bytes", n);
+            edit.discard();
+        }
+        Err(e) => {
+            print_error(e);
+            edit.keep();
+        }
+    };
+}
+
+/// Create a file only the user can read, under a name that cannot be
+/// guessed, refusing to open anything already there such as a planted link
+fn private_temp_file(name: &str) -> std::io::Result<(PathBuf, File)> {
+    use std::hash::{BuildHasher, Hasher};
+
+    let mut options = File::options();
+    options.read(true).write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    for _ in 0..16 {
+        // Every RandomState is seeded afresh from the system's randomness
+        let unique = std::collections::hash_map::RandomState::new().build_hasher().finish();
+        let path = std::env::temp_dir().join(format!("rsftp-{:016x}-{}", unique, name));
+        match options.open(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Err(std::io::Error::new(
+        std::io::ErrorKind::AlreadyExists,
+        "no unused temporary file name found",
+    ))
+}
+
+/// What `edit` compares to notice a remote file changing: its modification
+/// time and size, where the server reports them
+fn remote_stamp<T: IsFtpStream>(fs: &mut T, remote_file: &str) -> (Option<NaiveDateTime>, Option<usize>) {
+    (fs.mdtm(remote_file).ok(), fs.size(remote_file).ok())
+}
+
+/// Only a time or size known both times can show a change; one that could
+/// not be had, e.g. because the connection dropped, is taken as unknown
+fn stamp_changed(
+    before: (Option<NaiveDateTime>, Option<usize>),
+    after: (Option<NaiveDateTime>, Option<usize>),
+) -> bool {
+    fn differs<V: PartialEq>(a: Option<V>, b: Option<V>) -> bool {
+        matches!((a, b), (Some(a), Some(b)) if a != b)
+    }
+    differs(before.0, after.0) || differs(before.1, after.1)
+}
+
+pub fn cat<T: IsFtpStream>(fs: &mut T, remote_file: &str, settings: &Settings) {
+    let file_type = settings.transfer_mode.file_type(remote_file);
+    let result = fs.transfer_type(file_type.clone()).and_then(|_| {
+        fs.retr_until(remote_file, |stream| {
+            let mut stdout = std::io::stdout().lock();
+            let mut stdout = Local(&mut stdout);
+            match file_type {
+                FileType::Binary => std::io::copy(stream, &mut stdout),
+                _ => transfer::copy_to_local(stream, &mut stdout),
+            }
+            .map(|_| true)
+            .or_else(stopped_reading)
+        })
+    });
+    if let Err(e) = result {
+        print_error(e);
+    }
+}
+
+/// Print the first `lines` lines of a remote file, cancelling the download
+/// as soon as they have arrived
+pub fn head<T: IsFtpStream>(fs: &mut T, remote_file: &str, lines: usize, settings: &Settings) {
+    let file_type = settings.transfer_mode.file_type(remote_file);
+    let result = fs.transfer_type(file_type).and_then(|_| {
+        fs.retr_until(remote_file, |stream| {
+            let mut reader = std::io::BufReader::new(stream);
+            let mut line = Vec::new();
+            for _ in 0..lines {
+                line.clear();
+                if reader.read_until(b'\n', &mut line).map_err(FtpError::ConnectionError)? == 0 {
+                    return Ok(true);
+                }
+                print_line(&line);
+            }
+            // Whether the file happened to end right here
+            let rest = reader.fill_buf().map_err(FtpError::ConnectionError)?;
+            Ok(rest.is_empty())
+        })
+    });
+    if let Err(e) = result {
+        print_error(e);
+    }
+}
+
+/// Print the last `lines` lines of a remote file. With REST only the end of
+/// the file is fetched, reaching further back until enough lines turn up.
+pub fn tail<T: IsFtpStream>(fs: &mut T, remote_file: &str, lines: usize) {
+    // Offsets only make sense if the bytes go over unchanged
+    let result = fs.transfer_type(FileType::Binary).and_then(|_| {
+        if !client::has_feature(fs, "REST") {
+            let mut data = Vec::new();
+            fs.retr(remote_file, |stream| {
+                stream.read_to_end(&mut data).map_err(FtpError::ConnectionError)
+            })?;
+            return Ok(data);
+        }
+
+        let size = fs.size(remote_file)?;
+        let mut chunk = 8192;
+        loop {
+            let offset = size.saturating_sub(chunk);
+            let mut data = Vec::new();
+            fs.resume_transfer(offset)?;
+            fs.retr(remote_file, |stream| {
+                stream.read_to_end(&mut data).map_err(FtpError::ConnectionError)
+            })?;
+            // More newlines than lines wanted means the first of them, which
+            // may have started before `offset`, is not needed
+            if offset == 0 || data.iter().filter(|b| **b == b'\n').count() > lines {
+                return Ok(data);
+            }
+            chunk *= 4;
+        }
+    });
+    match result {
+        Ok(data) => {
+            let all = data.split_inclusive(|b| *b == b'\n').collect::<Vec<_>>();
+            for line in &all[all.len().saturating_sub(lines)..] {
+                print_line(line);
+            }
+        }
+        Err(e) => {
+            print_error(e);
+        }
+    };
+}
+
+/// Show a remote file in `$PAGER`
+pub fn page<T: IsFtpStream>(fs: &mut T, remote_file: &str, settings: &Settings) {
+    let pager = std::env::var("PAGER").unwrap_or_else(|_| String::from("less"));
+    get_piped(fs, remote_file, &pager, settings);
+}
+
+/// Print a line of a remote file, whatever its line ending
+fn print_line(line: &[u8]) {
+    let line = String::from_utf8_lossy(line);
+    println!("{}", line.trim_end_matches(['\r', '\n']));
+}
+
+/// Stream a remote file into the standard input of a local shell command
+pub fn get_piped<T: IsFtpStream>(fs: &mut T, remote_file: &str, command: &str, settings: &Settings) {
+    let file_type = settings.transfer_mode.file_type(remote_file);
+    let result = fs.transfer_type(file_type.clone()).and_then(|_| {
+        pipe_to(command, |stdin| {
+            fs.retr_until(remote_file, |stream| {
+                let mut stdin = Local(&mut *stdin);
+                match file_type {
+                    FileType::Binary => std::io::copy(stream, &mut stdin),
+                    _ => transfer::copy_to_local(stream, &mut stdin),
+                }
+                .map(|_| true)
+                .or_else(stopped_reading)
+            })
+        })
+    });
+    if let Err(e) = result {
+        print_error(e);
+    }
+}
+
+/// `ls` into the standard input of a local shell command: one plain,
+/// uncoloured entry per line, as ls does when its output is not a terminal
+pub fn ls_piped<T: IsFtpStream>(fs: &mut T, remote_dir: &str, opts: &ListOptions, command: &str) {
+    let result = fs.retrying(|fs| listing::list(fs, remote_dir, opts.all)).and_then(|entries| {
+        let opts = ListOptions {
+            one_per_line: true,
+            ..*opts
+        };
+        let entries = listing::arrange(entries, &opts);
+        pipe_to(command, |stdin| {
+            for line in listing::render(&entries, &opts) {
+                if let Err(e) = writeln!(stdin, "{}", strip_ansi(&line)) {
+                    return stopped_reading(retry::local(e)).map(|_| ());
+                }
+            }
+            Ok(())
+        })
+    });
+    if let Err(e) = result {
+        print_error(e);
+    }
+}
+
+pub fn shell(command: &str) {
+    let status = if command.trim().is_empty() {
+        // A bare ! starts an interactive shell
+        let shell = std::env::var("SHELL").unwrap_or_else(|_| String::from("sh"));
+        std::process::Command::new(shell).status()
+    } else {
+        shell_command(command).status()
+    };
+    match status {
+        Ok(status) if !status.success() => {
+            println!("{}", Color::Yellow.paint(format!("[*] Command exited with {}", status)));
+        }
         Ok(_) => {}
+        Err(e) => {
+            println!("{}", Color::Red.paint(format!("[-] {}", e)));
+        }
+    };
+}
+
+/// `command` run by the platform's shell
+fn shell_command(command: &str) -> std::process::Command {
+    let mut cmd = if cfg!(windows) {
+        let mut cmd = std::process::Command::new("cmd");
+        cmd.arg("/C");
+        cmd
+    } else {
+        let mut cmd = std::process::Command::new("sh");
+        cmd.arg("-c");
+        cmd
+    };
+    cmd.arg(command);
+    cmd
+}
+
+/// Start `command` with a pipe as its standard input, hand the pipe to
+/// `feed` and wait for the command to finish once it is closed
+fn pipe_to<F>(command: &str, feed: F) -> FtpResult<()>
+where
+    F: FnOnce(&mut std::process::ChildStdin) -> FtpResult<()>,
+{
+    let mut child = shell_command(command)
+        .stdin(std::process::Stdio::piped())
+        .spawn()
+        .map_err(retry::local_error)?;
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let result = feed(&mut stdin);
+    drop(stdin);
+    let _ = child.wait();
+    result
+}
+
+/// A command that exits, like `head`, closes the pipe before all of the
+/// input is written, which just means it has seen enough
+fn stopped_reading(e: std::io::Error) -> FtpResult<bool> {
+    match e.kind() {
+        std::io::ErrorKind::BrokenPipe if retry::is_local(&e) => Ok(false),
+        _ => Err(FtpError::ConnectionError(e)),
+    }
+}
+
+fn strip_ansi(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // Skip the escape sequence up to and including its final letter
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+pub fn chmod<T: IsFtpStream>(fs: &mut T, mode: &str, remote_file: &str) {
+    match fs.site(format!("CHMOD {} {}", mode, remote_file)) {
+        Ok(_) => {
+            println!("{}", Color::Green.paint("[+] Success"));
+        }
         Err(e) => {
             print_error(e);
         }
     };
 }
 
-pub fn user<T: IsFtpStream>(fs: &mut T, user: &str, pass: &str, cache: &mut String) {
-    match fs.login(user, pass) {
-        Ok(()) => {
-            *cache = user.to_string();
+pub fn site<T: IsFtpStream>(fs: &mut T, command: &str) {
+    match fs.site(command) {
+        Ok(response) => {
+            print_reply(&response);
+        }
+        Err(e) => {
+            print_error(e);
+        }
+    };
+}
+
+pub fn quote<T: IsFtpStream>(fs: &mut T, command: &str) {
+    match fs.quote(command) {
+        Ok(response) | Err(FtpError::UnexpectedResponse(response)) => {
+            let color = match response.status.code() {
+                100..=399 => Color::Green,
+                _ => Color::Red,
+            };
+            let text = String::from_utf8_lossy(&response.body);
+            println!("{}", color.paint(text.trim_end()));
+        }
+        Err(e) => {
+            print_error(e);
+        }
+    };
+}
+
+pub fn debug(enable: Option<bool>) {
+    let enabled = enable.unwrap_or(!trace::is_enabled());
+    trace::set_enabled(enabled);
+    println!(
+        "Debugging {}",
+        if enabled { Color::Green.paint("on") } else { Color::Red.paint("off") }
+    );
+}
+
+pub fn feat<T: IsFtpStream>(fs: &mut T) {
+    match fs.feat() {
+        Ok(features) if features.is_empty() => {
+            println!("{}", Color::Yellow.paint("[*] The server did not list any features"));
+        }
+        Ok(features) => {
+            let mut features = features.into_iter().collect::<Vec<_>>();
+            features.sort();
+            println!("{}", Color::White.dimmed().paint("Features supported by the server:"));
+            for (name, value) in features {
+                println!("  {} {}", Color::Green.paint(name), value.unwrap_or_default());
+            }
+        }
+        Err(e) => {
+            print_error(e);
+        }
+    };
+}
+
+pub fn system<T: IsFtpStream>(fs: &mut T) {
+    match fs.system() {
+        Ok(system) => {
+            println!("{}: {}", Color::White.dimmed().paint("Remote system type"), system);
+        }
+        Err(e) => {
+            print_error(e);
+        }
+    };
+}
+
+pub fn rhelp<T: IsFtpStream>(fs: &mut T, command: Option<&str>) {
+    match fs.help(command) {
+        Ok(response) => {
+            print_reply(&response);
+        }
+        Err(e) => {
+            print_error(e);
         }
+    };
+}
+
+pub fn transfer_mode(settings: &mut Settings, mode: Option<TransferMode>) {
+    if let Some(mode) = mode {
+        settings.transfer_mode = mode;
+    }
+    println!(
+        "{}: {}",
+        Color::White.dimmed().paint("Transfer mode"),
+        Color::Green.paint(settings.transfer_mode.name())
+    );
+}
+
+pub fn conflict_policy(settings: &mut Settings, policy: Option<ConflictPolicy>) {
+    if let Some(policy) = policy {
+        settings.conflict = policy;
+    }
+    println!(
+        "{}: {}",
+        Color::White.dimmed().paint("Conflict policy"),
+        Color::Green.paint(settings.conflict.name())
+    );
+}
+
+pub fn user<T: IsFtpStream>(fs: &mut T, user: &str, pass: &str) {
+    match fs.login(user, pass) {
+        Ok(()) => {}
         Err(e) => {
             print_error(e);
         }
@@ -194,12 +1632,72 @@ pub fn noop<T: IsFtpStream>(fs: &mut T) {
     };
 }
 
+/// Print the text of a server reply, every line of it
+fn print_reply(response: &Response) {
+    let text = String::from_utf8_lossy(&response.body);
+    println!("{}", Color::White.dimmed().paint(text.trim_end()));
+}
+
+/// Create a new file to download into, readable as well so that it can be
+/// checked against the server afterwards. A leftover from an earlier,
+/// interrupted download is replaced, but never followed if it is a link.
+fn create_local(path: &std::path::Path) -> std::io::Result<File> {
+    let mut options = File::options();
+    options.read(true).write(true).create_new(true);
+    match options.open(path) {
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+            std::fs::remove_file(path)?;
+            options.open(path)
+        }
+        opened => opened,
+    }
+}
+
+/// The permission bits of a local file, where the platform has them
+#[cfg(unix)]
+fn local_mode(file: &File) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    file.metadata().ok().map(|m| m.permissions().mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn local_mode(_file: &File) -> Option<u32> {
+    None
+}
+
+/// Ask a yes/no question on the terminal, defaulting to no
+fn confirm(question: &str) -> bool {
+    print!("{} [y/N] ", Color::White.bold().paint(question));
+    let _ = std::io::stdout().flush();
+    let mut answer = String::new();
+    if std::io::stdin().read_line(&mut answer).is_err() {
+        return false;
+    }
+    matches!(answer.trim(), "y" | "Y" | "yes")
+}
+
+fn plural(n: usize, one: &str, many: &str) -> String {
+    format!("{} {}", n, if n == 1 { one } else { many })
+}
+
 fn print_error(e: FtpError) {
+    if client::is_timeout(&e) {
+        println!("{}", Color::Red.paint("[-]: Timed out waiting for the server"));
+        return;
+    }
+    // Checksum mismatches, local and permanent errors, which are no fault of
+    // the connection
+    if let FtpError::ConnectionError(e) = &e {
+        if checksum::is_mismatch(e) || retry::is_local(e) || retry::is_permanent(e) {
+            println!("{}", Color::Red.paint(format!("[-]: {}", e)));
+            return;
+        }
+    }
     println!("{}", Color::Red.paint(format!("[-]: {}", e)));
 }
 
 pub fn help(cmd: &str) {
-    if cmd == "" { return; }
+    if cmd.is_empty() { return; }
     match cmd {
         "pwd" => {
             println!("USAGE:\n\t {}", Color::White.bold().paint("pwd")); 
@@ -209,6 +1707,66 @@ pub fn help(cmd: &str) {
             println!("USAGE:\n\t {}", Color::White.bold().paint("lpwd")); 
             println!("Print the working directory on the local machine");
         }
+        "!" => {
+            println!("USAGE:\n\t {}[COMMAND]", Color::White.bold().paint("!"));
+            println!("Run COMMAND in a local shell, or start an interactive shell if no COMMAND is given");
+        }
+        "|" => {
+            println!("USAGE:\n\t {} REMOTE_FILE | COMMAND\n\t {} [FLAGS] [REMOTE_DIR] | COMMAND", Color::White.bold().paint("get"), Color::White.bold().paint("ls"));
+            println!("Write a remote file, or a listing with one entry per line, to the standard input of a local shell COMMAND instead of a file or the terminal");
+        }
+        "edit" => {
+            println!("USAGE:\n\t {} [-f] REMOTE_FILE", Color::White.bold().paint("edit"));
+            println!("Open a copy of a remote file in $VISUAL or $EDITOR and upload it again if it was changed. If the remote file was modified in the meantime nothing is uploaded, unless -f is given");
+        }
+        "cat" => {
+            println!("USAGE:\n\t {} REMOTE_FILES...", Color::White.bold().paint("cat"));
+            println!("Print the contents of remote files");
+        }
+        "head" => {
+            println!("USAGE:\n\t {} [-n LINES] REMOTE_FILE", Color::White.bold().paint("head"));
+            println!("Print the first LINES (10 by default) lines of a remote file, without downloading the rest of it");
+        }
+        "tail" => {
+            println!("USAGE:\n\t {} [-n LINES] REMOTE_FILE", Color::White.bold().paint("tail"));
+            println!("Print the last LINES (10 by default) lines of a remote file. If the server supports REST, only the end of the file is downloaded");
+        }
+        "page" | "less" => {
+            println!("USAGE:\n\t {} REMOTE_FILE", Color::White.bold().paint("page|less"));
+            println!("View a remote file in the pager named by $PAGER, less by default");
+        }
+        "lcd" => {
+            println!("USAGE:\n\t {} [LOCAL_DIR|-]", Color::White.bold().paint("lcd"));
+            println!("Change the working directory on the local machine, where downloads are saved. Without LOCAL_DIR the home directory is used, - goes back to the previous directory and ~ stands for the home directory");
+        }
+        "lls" => {
+            println!("USAGE:\n\t {} [-l1ahStr] [LOCAL_DIR]", Color::White.bold().paint("lls"));
+            println!("List a directory on the local machine, taking the same flags as ls");
+        }
+        "lmkdir" => {
+            println!("USAGE:\n\t {} LOCAL_DIRS...", Color::White.bold().paint("lmkdir"));
+            println!("Create directories on the local machine");
+        }
+        "hash" => {
+            println!("USAGE:\n\t {} [-a sha256|sha1|md5|crc32] REMOTE_FILE [LOCAL_FILE]", Color::White.bold().paint("hash"));
+            println!("Show the checksum the server computes for a remote file, with HASH or the older XSHA1/XMD5/XCRC commands, and compare it with LOCAL_FILE if given");
+        }
+        "verify" => {
+            println!("USAGE:\n\t {} [on|off]", Color::White.bold().paint("verify"));
+            println!("Turn checksum verification of every get and put on or off, or toggle it. A transfer whose checksums differ fails");
+        }
+        "conflict" => {
+            println!("USAGE:\n\t {} [ask|skip|overwrite|rename|newer]", Color::White.bold().paint("conflict"));
+            println!("Show or set what get and put do when the file they would write exists: ask each time, skip it, overwrite it, rename the new file to NAME.1.EXT and so on, or overwrite it only if it is older than the file being transferred");
+        }
+        "atomic" => {
+            println!("USAGE:\n\t {} [on|off]", Color::White.bold().paint("atomic"));
+            println!("Turn atomic uploads on or off, or toggle them. Files are then uploaded as .NAME.rsftp-part and only renamed to NAME once complete and, with verify on, checked; a failed upload is removed");
+        }
+        "lrm" => {
+            println!("USAGE:\n\t {} LOCAL_FILES...", Color::White.bold().paint("lrm"));
+            println!("Delete files on the local machine");
+        }
         "cd" | "cwd" => { 
             println!("USAGE:\n\t {} REMOTE_DIR", Color::White.bold().paint("cd|cwd")); 
             println!("Change the working directory on the remote machine to remote-directory");
@@ -222,8 +1780,27 @@ pub fn help(cmd: &str) {
             println!("Move up one directory. This is equivalent to executing 'cd ..'");
         }
         "ls" | "dir" => {
-            println!("USAGE:\n\t {} [REMOTE_DIR|REMOTE_FILE]", Color::White.bold().paint("ls|dir")); 
+            println!("USAGE:\n\t {} [-l1ahStr] [REMOTE_DIR|REMOTE_FILE]", Color::White.bold().paint("ls|dir")); 
             println!("Print a listing of the contents of a directory on the remote machine. Regardless of the argument being a file or directory, human readable listing is printed");
+            println!("\t-l  long format with permissions, owner, size and modification time");
+            println!("\t-1  one name per line");
+            println!("\t-a  include entries whose names begin with a dot; without MLSD this sends LIST -a, which some servers ignore");
+            println!("\t-h  human readable sizes (with -l)");
+            println!("\t-S  sort by size, largest first");
+            println!("\t-t  sort by modification time, newest first");
+            println!("\t-r  reverse the sort order");
+        }
+        "tree" => {
+            println!("USAGE:\n\t {} [-L DEPTH] [-d] [REMOTE_DIR]", Color::White.bold().paint("tree"));
+            println!("Recursively list REMOTE_DIR (the current directory by default) as a tree, followed by the number of directories and files found. -L limits how deep the listing goes and -d shows directories only");
+        }
+        "du" => {
+            println!("USAGE:\n\t {} [-s] [-h] [--max-depth N] [REMOTE_DIR]", Color::White.bold().paint("du"));
+            println!("Print the total size in bytes of every directory below REMOTE_DIR (the current directory by default). -s prints only the grand total, -h uses human readable sizes and --max-depth N only prints directories at most N levels below REMOTE_DIR");
+        }
+        "find" => {
+            println!("USAGE:\n\t {} [REMOTE_DIR] [-name PATTERN] [-type f|d|l] [-size [+|-]N[c|k|M|G]] [-mtime [+|-]DAYS] [-get|-delete [-f]]", Color::White.bold().paint("find"));
+            println!("Recursively search REMOTE_DIR (the current directory by default) and print every path passing all of the given tests. PATTERN may use the *, ? and [...] wildcards; +N means more than N and -N less than N. With -get matching files are downloaded into the local directory, numbered if several share a name, with -delete matches are removed from the remote machine after asking, or straight away with -f");
         }
         "append" => {
             println!("USAGE:\n\t {} LOCAL_FILE REMOTE_FILE", Color::White.bold().paint("append")); 
@@ -233,6 +1810,54 @@ pub fn help(cmd: &str) {
             println!("USAGE:\n\t {} REMOTE_FILES...", Color::White.bold().paint("delete|rm")); 
             println!("Delete files on the remote machine");
         }
+        "mdtm" | "modtime" => {
+            println!("USAGE:\n\t {} REMOTE_FILES...", Color::White.bold().paint("mdtm|modtime"));
+            println!("Print the last modification time of files on the remote machine");
+        }
+        "chmod" => {
+            println!("USAGE:\n\t {} MODE REMOTE_FILES...", Color::White.bold().paint("chmod"));
+            println!("Change the permissions of files on the remote machine to the octal MODE, e.g. 755, using SITE CHMOD");
+        }
+        "site" => {
+            println!("USAGE:\n\t {} ARGS...", Color::White.bold().paint("site"));
+            println!("Send a server specific SITE command and print the reply, e.g. site HELP");
+        }
+        "quote" | "literal" => {
+            println!("USAGE:\n\t {} COMMAND [ARGS...]", Color::White.bold().paint("quote|literal"));
+            println!("Send COMMAND to the server exactly as given and print its complete reply. Commands that need a data connection, like LIST or RETR, are not supported");
+        }
+        "type" => {
+            println!("USAGE:\n\t {} [binary|ascii|auto]", Color::White.bold().paint("type"));
+            println!("Show or set how files are transferred. binary, the default, copies files byte for byte; ascii converts line endings between the local and the network convention; auto uses ascii for files with a text extension like .txt or .csv and binary for everything else");
+        }
+        "ascii" => {
+            println!("USAGE:\n\t {}", Color::White.bold().paint("ascii"));
+            println!("Transfer files as text, converting line endings. Same as type ascii");
+        }
+        "binary" => {
+            println!("USAGE:\n\t {}", Color::White.bold().paint("binary"));
+            println!("Transfer files byte for byte. Same as type binary");
+        }
+        "feat" => {
+            println!("USAGE:\n\t {}", Color::White.bold().paint("feat"));
+            println!("List the optional features the server supports, as reported by FEAT when connecting");
+        }
+        "system" => {
+            println!("USAGE:\n\t {}", Color::White.bold().paint("system"));
+            println!("Show the type of operating system the server runs on");
+        }
+        "rhelp" => {
+            println!("USAGE:\n\t {} [COMMAND]", Color::White.bold().paint("rhelp"));
+            println!("Show the help the server gives for its commands, or for one COMMAND");
+        }
+        "debug" => {
+            println!("USAGE:\n\t {} [on|off]", Color::White.bold().paint("debug"));
+            println!("Show every command sent to and reply received from the server, with passwords masked. Without an argument debugging is toggled");
+        }
+        "rename" | "mv" => {
+            println!("USAGE:\n\t {} SOURCE DEST\n\t {} -r PATTERN REPLACEMENT", Color::White.bold().paint("rename|mv"), Color::White.bold().paint("rename|mv"));
+            println!("Rename SOURCE to DEST on the remote machine, or move it into DEST if that is an existing directory. With -r every entry of the current directory matching the regular expression PATTERN is renamed to REPLACEMENT, which may refer to capture groups as $1, $2, ...; the new names are shown for confirmation first");
+        }
         "rmdir" => {
             println!("USAGE:\n\t {} REMOTE_DIR", Color::White.bold().paint("rmdir")); 
             println!("Delete a directory on the remote machine");
@@ -242,12 +1867,12 @@ pub fn help(cmd: &str) {
             println!("Return size of REMOTE_FILE on remote machine");
         }
         "get" => {
-            println!("USAGE:\n\t {} REMOTE_FILE [LOCAL_FILE]", Color::White.bold().paint("get")); 
-            println!("Retrieve the REMOTE_FILE and store it on the local machine.  If the LOCAL_FILE name is not specified, it is given the same name it has on the remote machine");
+            println!("USAGE:\n\t {} [-p] [-c POLICY] [--] REMOTE_FILE [LOCAL_FILE|LOCAL_DIR]", Color::White.bold().paint("get")); 
+            println!("Retrieve the REMOTE_FILE and store it on the local machine.  If the LOCAL_FILE name is not specified, it is given the same name it has on the remote machine, without its directories, in the local working directory; a LOCAL_DIR receives it under that name. -p creates missing local directories. -c overrides the conflict policy for an existing LOCAL_FILE, see conflict. -- ends the options, for names starting with a dash");
         }
         "put" => {
-            println!("USAGE:\n\t {} LOCAL_FILE [REMOTE_FILE]", Color::White.bold().paint("put")); 
-            println!("Store a LOCAL_FILE on the remote machine.  If REMOTE_FILE is left unspecified then the name of LOCAL_FILE is used");
+            println!("USAGE:\n\t {} [-c POLICY] [--] LOCAL_FILE [REMOTE_FILE]", Color::White.bold().paint("put")); 
+            println!("Store a LOCAL_FILE on the remote machine.  If REMOTE_FILE is left unspecified then the name of LOCAL_FILE is used. -c overrides the conflict policy for an existing REMOTE_FILE, see conflict. -- ends the options, for names starting with a dash");
         }
         "user" => {
             println!("USAGE:\n\t {} USER", Color::White.bold().paint("user")); 
@@ -273,13 +1898,58 @@ pub fn help(cmd: &str) {
 }
 
 pub fn print_available_commands() {
-    let commands = [ "cd|cwd", "put", "get", "pwd", "ls|dir", "bye|exit|quit", "noop", "user", "size", "mkdir", "rmdir",  "delete|rm", "append", "cdup", "lpwd", "help"];
+    let commands = [ "cd|cwd", "put", "get", "pwd", "ls|dir", "bye|exit|quit", "noop", "user", "size", "mdtm|modtime", "mkdir", "rmdir",  "delete|rm", "append", "cdup", "lpwd", "lcd", "lls", "lmkdir", "lrm", "tree", "du", "find", "rename|mv", "chmod", "site", "quote|literal", "debug", "type", "ascii", "binary", "feat", "system", "rhelp", "!", "|", "cat", "head", "tail", "page|less", "edit", "hash", "verify", "atomic", "conflict", "help"];
 
     for (i, c) in commands.iter().enumerate() {
         if i%4 == 0 { println!(); }
         print!("{}\t", Color::White.bold().paint(*c));
-        std::io::stdout().flush(); 
+        let _ = std::io::stdout().flush();
     }
 
     println!();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn renames<'a>(pairs: &[(&'a str, &str)]) -> Vec<(&'a str, String)> {
+        pairs.iter().map(|(from, to)| (*from, to.to_string())).collect()
+    }
+
+    #[test]
+    fn renames_the_end_of_a_chain_first() {
+        let chain = renames(&[("a", "b"), ("b", "c")]);
+        assert_eq!(rename_order(&chain), Some(renames(&[("b", "c"), ("a", "b")])));
+
+        let chain = renames(&[("log1", "log2"), ("log2", "log3"), ("log3", "log4")]);
+        assert_eq!(
+            rename_order(&chain),
+            Some(renames(&[("log3", "log4"), ("log2", "log3"), ("log1", "log2")]))
+        );
+    }
+
+    #[test]
+    fn keeps_independent_renames_in_order() {
+        let independent = renames(&[("a", "x"), ("b", "y")]);
+        assert_eq!(rename_order(&independent), Some(independent.clone()));
+    }
+
+    #[test]
+    fn refuses_cycles() {
+        assert_eq!(rename_order(&renames(&[("a", "b"), ("b", "a")])), None);
+        assert_eq!(rename_order(&renames(&[("a", "b"), ("b", "c"), ("c", "a")])), None);
+    }
+
+    #[test]
+    fn finds_clashing_names() {
+        let existing = ["a", "b", "c", "keep"];
+        // Two files given the same name
+        assert_eq!(rename_clash(&existing, &renames(&[("a", "x"), ("b", "x")])), Some("x"));
+        // A file that stays where it is
+        assert_eq!(rename_clash(&existing, &renames(&[("a", "keep")])), Some("keep"));
+        // A name that is freed by another rename
+        assert_eq!(rename_clash(&existing, &renames(&[("a", "b"), ("b", "c"), ("c", "d")])), None);
+        assert_eq!(rename_clash(&existing, &renames(&[("a", "new")])), None);
+    }
+}
@@ -0,0 +1,113 @@
+use std::str::FromStr;
+
+/// What `get` and `put` do when the file they would write already exists
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ConflictPolicy {
+    /// Ask on the terminal every time
+    #[default]
+    Ask,
+    Skip,
+    Overwrite,
+    /// Write to a numbered name next to the existing file instead
+    Rename,
+    /// Overwrite only if the file being transferred is newer
+    Newer,
+}
+
+impl ConflictPolicy {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ConflictPolicy::Ask => "ask",
+            ConflictPolicy::Skip => "skip",
+            ConflictPolicy::Overwrite => "overwrite",
+            ConflictPolicy::Rename => "rename",
+            ConflictPolicy::Newer => "newer",
+        }
+    }
+}
+
+impl FromStr for ConflictPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ask" => Ok(ConflictPolicy::Ask),
+            "skip" => Ok(ConflictPolicy::Skip),
+            "overwrite" => Ok(ConflictPolicy::Overwrite),
+            "rename" => Ok(ConflictPolicy::Rename),
+            "newer" => Ok(ConflictPolicy::Newer),
+            _ => Err(format!(
+                "unknown conflict policy '{}', expected ask, skip, overwrite, rename or newer",
+                s
+            )),
+        }
+    }
+}
+
+/// The first of `name.1`, `name.2`, ... that is not `taken`, numbered before
+/// the extension: `dir/report.txt` becomes `dir/report.1.txt`
+pub fn numbered_name<F: FnMut(&str) -> bool>(name: &str, mut taken: F) -> String {
+    let base_start = name.rfind('/').map_or(0, |i| i + 1);
+    // A leading dot marks a hidden file rather than an extension
+    let (stem, extension) = match name[base_start..].rfind('.') {
+        Some(i) if i > 0 => name.split_at(base_start + i),
+        _ => (name, ""),
+    };
+    (1..)
+        .map(|n| format!("{}.{}{}", stem, n, extension))
+        .find(|candidate| !taken(candidate))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn numbered(name: &str, taken: &[&str]) -> String {
+        numbered_name(name, |candidate| taken.contains(&candidate))
+    }
+
+    #[test]
+    fn numbers_before_the_extension() {
+        assert_eq!(numbered("report.txt", &[]), "report.1.txt");
+        assert_eq!(numbered("archive.tar.gz", &[]), "archive.tar.1.gz");
+        assert_eq!(numbered("dir/report.txt", &[]), "dir/report.1.txt");
+    }
+
+    #[test]
+    fn numbers_names_without_extension_at_the_end() {
+        assert_eq!(numbered("README", &[]), "README.1");
+        assert_eq!(numbered(".bashrc", &[]), ".bashrc.1");
+        assert_eq!(numbered("dir/.bashrc", &[]), "dir/.bashrc.1");
+        assert_eq!(numbered(".config.toml", &[]), ".config.1.toml");
+    }
+
+    #[test]
+    fn ignores_dots_in_directories() {
+        assert_eq!(numbered("a.b/c", &[]), "a.b/c.1");
+        assert_eq!(numbered("a.b/c.d", &[]), "a.b/c.1.d");
+    }
+
+    #[test]
+    fn skips_taken_names() {
+        assert_eq!(
+            numbered("report.txt", &["report.1.txt", "report.2.txt"]),
+            "report.3.txt"
+        );
+    }
+
+    #[test]
+    fn parses_policies() {
+        for policy in [
+            ConflictPolicy::Ask,
+            ConflictPolicy::Skip,
+            ConflictPolicy::Overwrite,
+            ConflictPolicy::Rename,
+            ConflictPolicy::Newer,
+        ] {
+            assert_eq!(policy.name().parse::<ConflictPolicy>(), Ok(policy));
+        }
+        assert!("Overwrite".parse::<ConflictPolicy>().is_err());
+        assert!("".parse::<ConflictPolicy>().is_err());
+    }
+}
@@ -0,0 +1,288 @@
+use crate::ftp_handler::listing::{Entry, EntryKind};
+use chrono::Utc;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Compare {
+    Less,
+    Equal,
+    Greater,
+}
+
+impl Compare {
+    /// Split the `+`/`-` prefix `find` uses for "more than"/"less than"
+    fn split(s: &str) -> (Compare, &str) {
+        if let Some(rest) = s.strip_prefix('+') {
+            (Compare::Greater, rest)
+        } else if let Some(rest) = s.strip_prefix('-') {
+            (Compare::Less, rest)
+        } else {
+            (Compare::Equal, s)
+        }
+    }
+
+    fn test(&self, value: u64, against: u64) -> bool {
+        match self {
+            Compare::Less => value < against,
+            Compare::Equal => value == against,
+            Compare::Greater => value > against,
+        }
+    }
+}
+
+/// What `find` does with each match besides printing it
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum FindAction {
+    #[default]
+    Print,
+    Get,
+    Delete,
+}
+
+/// The tests of a `find` expression; an entry matches when it passes all
+/// of the ones given
+#[derive(Clone, Debug, Default)]
+pub struct Filter {
+    pub name: Option<String>,
+    pub kind: Option<EntryKind>,
+    /// Comparison, count and unit size in bytes
+    pub size: Option<(Compare, u64, u64)>,
+    /// Comparison and age in whole days
+    pub mtime: Option<(Compare, u64)>,
+}
+
+impl Filter {
+    /// `-type f` or `-type d`
+    pub fn parse_type(s: &str) -> Option<EntryKind> {
+        match s {
+            "f" => Some(EntryKind::File),
+            "d" => Some(EntryKind::Directory),
+            "l" => Some(EntryKind::Symlink),
+            _ => None,
+        }
+    }
+
+    /// `-size +10M`: `c` or no suffix for bytes, `k`, `M` and `G` for powers of 1024
+    pub fn parse_size(s: &str) -> Option<(Compare, u64, u64)> {
+        let (cmp, s) = Compare::split(s);
+        let (number, unit) = match s.char_indices().last()? {
+            (i, 'c') => (&s[..i], 1),
+            (i, 'k') | (i, 'K') => (&s[..i], 1 << 10),
+            (i, 'M') => (&s[..i], 1 << 20),
+            (i, 'G') => (&s[..i], 1 << 30),
+            _ => (s, 1),
+        };
+        Some((cmp, number.parse().ok()?, unit))
+    }
+
+    /// `-mtime -7`: modified less than seven days ago
+    pub fn parse_mtime(s: &str) -> Option<(Compare, u64)> {
+        let (cmp, s) = Compare::split(s);
+        Some((cmp, s.parse().ok()?))
+    }
+
+    pub fn matches(&self, entry: &Entry) -> bool {
+        if let Some(pattern) = &self.name {
+            if !glob_match(pattern, &entry.name) {
+                return false;
+            }
+        }
+        if let Some(kind) = self.kind {
+            if entry.kind != kind {
+                return false;
+            }
+        }
+        if let Some((cmp, count, unit)) = self.size {
+            // Like find, sizes are rounded up to whole units before comparing
+            match entry.size {
+                Some(size) if cmp.test(size.div_ceil(unit), count) => {}
+                _ => return false,
+            }
+        }
+        if let Some((cmp, days)) = self.mtime {
+            let age = entry
+                .modified
+                .map(|m| (Utc::now().naive_utc() - m).num_days().max(0) as u64);
+            match age {
+                Some(age) if cmp.test(age, days) => {}
+                _ => return false,
+            }
+        }
+        true
+    }
+}
+
+/// Shell style wildcard match supporting `*`, `?` and `[...]` classes. A
+/// mismatch only goes back to the latest `*`, letting it take one more
+/// character, so the work is bounded by the lengths of the two multiplied.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+    let (mut p, mut n) = (0, 0);
+    // Where matching resumes after the latest `*`: pattern and name index
+    let mut star = None;
+    while n < name.len() {
+        if pattern.get(p) == Some(&'*') {
+            p += 1;
+            star = Some((p, n));
+            continue;
+        }
+        if let Some(len) = match_one(&pattern[p..], name[n]) {
+            p += len;
+            n += 1;
+            continue;
+        }
+        match star {
+            Some((star_p, star_n)) => {
+                p = star_p;
+                n = star_n + 1;
+                star = Some((star_p, n));
+            }
+            None => return false,
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// How much of `pattern` matches the single character `c`: a `?`, a
+/// `[...]` class or a literal. `None` if it does not match.
+fn match_one(pattern: &[char], c: char) -> Option<usize> {
+    match pattern.first()? {
+        '?' => Some(1),
+        '[' => match pattern.iter().position(|p| *p == ']') {
+            Some(end) if end > 1 => {
+                let class = &pattern[1..end];
+                let (negated, class) = match class.first() {
+                    Some('!') | Some('^') => (true, &class[1..]),
+                    _ => (false, class),
+                };
+                let mut found = false;
+                let mut i = 0;
+                while i < class.len() {
+                    if i + 2 < class.len() && class[i + 1] == '-' {
+                        found |= (class[i]..=class[i + 2]).contains(&c);
+                        i += 3;
+                    } else {
+                        found |= class[i] == c;
+                        i += 1;
+                    }
+                }
+                (found != negated).then_some(end + 1)
+            }
+            // An unterminated class is a literal '['
+            _ => (c == '[').then_some(1),
+        },
+        p => (*p == c).then_some(1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, NaiveDateTime};
+
+    fn entry(size: Option<u64>, modified: Option<NaiveDateTime>) -> Entry {
+        Entry {
+            name: String::from("file"),
+            kind: EntryKind::File,
+            size,
+            modified,
+            permissions: None,
+            owner: None,
+            group: None,
+            target: None,
+        }
+    }
+
+    fn size_filter(s: &str) -> Filter {
+        Filter {
+            size: Filter::parse_size(s),
+            ..Filter::default()
+        }
+    }
+
+    #[test]
+    fn matches_wildcards() {
+        assert!(glob_match("*.txt", "notes.txt"));
+        assert!(glob_match("*.txt", ".txt"));
+        assert!(!glob_match("*.txt", "notes.txt.bak"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("a*b*c", "aXbYbc"));
+        assert!(glob_match("file?.log", "file1.log"));
+        assert!(!glob_match("file?.log", "file.log"));
+        assert!(!glob_match("file?.log", "file10.log"));
+        assert!(glob_match("", ""));
+        assert!(!glob_match("", "a"));
+    }
+
+    #[test]
+    fn matches_classes() {
+        assert!(glob_match("[a-c]*", "banana"));
+        assert!(!glob_match("[a-c]*", "dates"));
+        assert!(glob_match("v[0-9].[0-9]", "v1.2"));
+        assert!(glob_match("[!x]y", "ay"));
+        assert!(!glob_match("[!x]y", "xy"));
+        assert!(glob_match("[^x]y", "ay"));
+        assert!(glob_match("[abc]", "b"));
+        assert!(!glob_match("[abc]", "d"));
+    }
+
+    #[test]
+    fn takes_an_unterminated_class_literally() {
+        assert!(glob_match("[abc", "[abc"));
+        assert!(!glob_match("[abc", "a"));
+        assert!(glob_match("*[", "x["));
+    }
+
+    #[test]
+    fn backtracks_without_blowing_up() {
+        let name = "a".repeat(200);
+        assert!(!glob_match("*a*a*a*a*a*a*a*a*b", &name));
+        assert!(glob_match("*a*a*a*a*a*a*a*a*a", &name));
+    }
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(Filter::parse_size("+10M"), Some((Compare::Greater, 10, 1 << 20)));
+        assert_eq!(Filter::parse_size("-2k"), Some((Compare::Less, 2, 1 << 10)));
+        assert_eq!(Filter::parse_size("3G"), Some((Compare::Equal, 3, 1 << 30)));
+        assert_eq!(Filter::parse_size("1c"), Some((Compare::Equal, 1, 1)));
+        assert_eq!(Filter::parse_size("512"), Some((Compare::Equal, 512, 1)));
+        assert_eq!(Filter::parse_size(""), None);
+        assert_eq!(Filter::parse_size("M"), None);
+        assert_eq!(Filter::parse_size("ten"), None);
+    }
+
+    #[test]
+    fn rounds_sizes_up_to_whole_units() {
+        let ten_mib = 10 << 20;
+        assert!(!size_filter("+10M").matches(&entry(Some(ten_mib), None)));
+        assert!(size_filter("+10M").matches(&entry(Some(ten_mib + 1), None)));
+        assert!(size_filter("10M").matches(&entry(Some(ten_mib - 1), None)));
+        assert!(size_filter("1c").matches(&entry(Some(1), None)));
+        assert!(!size_filter("1c").matches(&entry(Some(2), None)));
+        // As with find, one byte already counts as a whole unit
+        assert!(size_filter("-1M").matches(&entry(Some(0), None)));
+        assert!(!size_filter("-1M").matches(&entry(Some(1), None)));
+        // Entries without a size never match
+        assert!(!size_filter("-1M").matches(&entry(None, None)));
+    }
+
+    #[test]
+    fn parses_and_compares_ages() {
+        assert_eq!(Filter::parse_mtime("-7"), Some((Compare::Less, 7)));
+        assert_eq!(Filter::parse_mtime("+30"), Some((Compare::Greater, 30)));
+        assert_eq!(Filter::parse_mtime("3"), Some((Compare::Equal, 3)));
+        assert_eq!(Filter::parse_mtime("week"), None);
+
+        let filter = |s| Filter {
+            mtime: Filter::parse_mtime(s),
+            ..Filter::default()
+        };
+        let days_ago = |days: i64| entry(None, Some(Utc::now().naive_utc() - Duration::hours(days * 24 + 1)));
+        assert!(filter("-7").matches(&days_ago(2)));
+        assert!(!filter("-7").matches(&days_ago(7)));
+        assert!(filter("+30").matches(&days_ago(31)));
+        assert!(filter("3").matches(&days_ago(3)));
+        assert!(!filter("3").matches(&entry(None, None)));
+    }
+}
@@ -0,0 +1,664 @@
+use crate::ftp_handler::client::{self, IsFtpStream};
+use ansi_term::Color;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use std::fs::Metadata;
+use std::io;
+use std::path::Path;
+use suppaftp::{FtpError, FtpResult};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EntryKind {
+    File,
+    Directory,
+    Symlink,
+    Other,
+}
+
+impl EntryKind {
+    /// The type character used in the first column of `ls -l`
+    pub fn as_char(&self) -> char {
+        match self {
+            EntryKind::File => '-',
+            EntryKind::Directory => 'd',
+            EntryKind::Symlink => 'l',
+            EntryKind::Other => '?',
+        }
+    }
+}
+
+/// One entry of a directory listing, whichever format the server sent it in
+#[derive(Clone, Debug)]
+pub struct Entry {
+    pub name: String,
+    pub kind: EntryKind,
+    pub size: Option<u64>,
+    pub modified: Option<NaiveDateTime>,
+    /// `rwxr-xr-x` style permission bits, without the type character
+    pub permissions: Option<String>,
+    pub owner: Option<String>,
+    pub group: Option<String>,
+    /// Where a symlink points to
+    pub target: Option<String>,
+}
+
+impl Entry {
+    pub fn is_dir(&self) -> bool {
+        self.kind == EntryKind::Directory
+    }
+
+    fn new(name: &str, kind: EntryKind) -> Self {
+        Entry {
+            name: name.to_string(),
+            kind,
+            size: None,
+            modified: None,
+            permissions: None,
+            owner: None,
+            group: None,
+            target: None,
+        }
+    }
+
+    /// Parse an RFC 3659 MLSD/MLST line: `fact=value;fact=value; name`.
+    /// The `.` and `..` entries (cdir/pdir) yield `None`.
+    pub fn from_mlsx_line(line: &str) -> Option<Entry> {
+        let (facts, name) = line.trim_start().split_once(' ')?;
+        let mut entry = Entry::new(name, EntryKind::Other);
+
+        for fact in facts.split(';').filter(|f| !f.is_empty()) {
+            let (key, value) = fact.split_once('=').unwrap_or((fact, ""));
+            match key.to_ascii_lowercase().as_str() {
+                "type" => match value.to_ascii_lowercase().as_str() {
+                    "cdir" | "pdir" => return None,
+                    "file" => entry.kind = EntryKind::File,
+                    "dir" => entry.kind = EntryKind::Directory,
+                    t if t.starts_with("os.unix=slink") || t.starts_with("os.unix=symlink") => {
+                        entry.kind = EntryKind::Symlink;
+                        entry.target = value.split_once(':').map(|(_, t)| t.to_string());
+                    }
+                    _ => {}
+                },
+                "size" | "sizd" => entry.size = value.parse().ok(),
+                "modify" => {
+                    entry.modified = value
+                        .get(..14)
+                        .and_then(|v| NaiveDateTime::parse_from_str(v, "%Y%m%d%H%M%S").ok())
+                }
+                "unix.mode" => {
+                    entry.permissions = u32::from_str_radix(value, 8).ok().map(mode_string)
+                }
+                "unix.owner" | "unix.ownername" | "unix.uid" if entry.owner.is_none() => {
+                    entry.owner = Some(value.to_string())
+                }
+                "unix.group" | "unix.groupname" | "unix.gid" if entry.group.is_none() => {
+                    entry.group = Some(value.to_string())
+                }
+                _ => {}
+            }
+        }
+        Some(entry)
+    }
+
+    /// Parse a line of LIST output, trying the Unix `ls -l` layout first and
+    /// then the DOS/IIS one. Lines that match neither are kept verbatim so
+    /// nothing the server sent is hidden.
+    pub fn from_list_line(line: &str) -> Option<Entry> {
+        if line.starts_with("total ") {
+            return None;
+        }
+        let entry = Self::from_unix_line(line)
+            .or_else(|| Self::from_dos_line(line))
+            .unwrap_or_else(|| Entry::new(line, EntryKind::Other));
+        match entry.name.as_str() {
+            "." | ".." => None,
+            _ => Some(entry),
+        }
+    }
+
+    /// `drwxr-xr-x 2 owner group 4096 Oct 19 06:34 name`, with the link count
+    /// or group missing on some servers
+    fn from_unix_line(line: &str) -> Option<Entry> {
+        let fields = fields(line);
+        let mode = fields.first()?.1;
+        let kind = match mode.chars().next()? {
+            '-' => EntryKind::File,
+            'd' => EntryKind::Directory,
+            'l' => EntryKind::Symlink,
+            'b' | 'c' | 'p' | 's' => EntryKind::Other,
+            _ => return None,
+        };
+        let permissions = mode.get(1..10)?;
+        if !permissions.chars().all(|c| "rwxsStTl-".contains(c)) {
+            return None;
+        }
+
+        // The date is the only fixed point: month, day, then time or year
+        let date_at = (3..fields.len().saturating_sub(3)).find(|&i| {
+            month(fields[i].1).is_some()
+                && fields[i + 1].1.parse::<u32>().is_ok()
+                && fields[i + 2].1.contains(|c: char| c.is_ascii_digit())
+        })?;
+        let name_at = fields.get(date_at + 3)?.0;
+
+        let mut entry = Entry::new(&line[name_at..], kind);
+        entry.permissions = Some(permissions.to_string());
+        entry.size = fields[date_at - 1].1.parse().ok();
+        entry.modified = unix_date(
+            fields[date_at].1,
+            fields[date_at + 1].1,
+            fields[date_at + 2].1,
+        );
+        let owner_at = if fields[1].1.parse::<u64>().is_ok() { 2 } else { 1 };
+        if owner_at < date_at - 1 {
+            entry.owner = Some(fields[owner_at].1.to_string());
+        }
+        if owner_at + 1 < date_at - 1 {
+            entry.group = Some(fields[owner_at + 1].1.to_string());
+        }
+        if kind == EntryKind::Symlink {
+            if let Some((name, target)) = entry.name.clone().split_once(" -> ") {
+                entry.name = name.to_string();
+                entry.target = Some(target.to_string());
+            }
+        }
+        Some(entry)
+    }
+
+    /// `10-19-20  03:19PM  <DIR>  name` or `10-19-2020  15:19  1234  name`
+    fn from_dos_line(line: &str) -> Option<Entry> {
+        let fields = fields(line);
+        if fields.len() < 4 {
+            return None;
+        }
+        let date = NaiveDate::parse_from_str(fields[0].1, "%m-%d-%y")
+            .or_else(|_| NaiveDate::parse_from_str(fields[0].1, "%m-%d-%Y"))
+            .ok()?;
+        let time = NaiveTime::parse_from_str(fields[1].1, "%I:%M%p")
+            .or_else(|_| NaiveTime::parse_from_str(fields[1].1, "%H:%M"))
+            .ok()?;
+
+        let name = &line[fields[3].0..];
+        let mut entry = if fields[2].1.eq_ignore_ascii_case("<DIR>") {
+            Entry::new(name, EntryKind::Directory)
+        } else {
+            let mut entry = Entry::new(name, EntryKind::File);
+            entry.size = Some(fields[2].1.replace(',', "").parse().ok()?);
+            entry
+        };
+        entry.modified = Some(date.and_time(time));
+        Some(entry)
+    }
+
+    fn from_local(name: &str, path: &Path, metadata: &Metadata) -> Self {
+        let file_type = metadata.file_type();
+        let kind = if file_type.is_symlink() {
+            EntryKind::Symlink
+        } else if file_type.is_dir() {
+            EntryKind::Directory
+        } else if file_type.is_file() {
+            EntryKind::File
+        } else {
+            EntryKind::Other
+        };
+        let mut entry = Entry::new(name, kind);
+        entry.size = Some(metadata.len());
+        entry.modified = metadata
+            .modified()
+            .ok()
+            .map(|t| DateTime::<Utc>::from(t).naive_utc());
+        if kind == EntryKind::Symlink {
+            entry.target = std::fs::read_link(path)
+                .ok()
+                .map(|t| t.display().to_string());
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            entry.permissions = Some(mode_string(metadata.mode()));
+            entry.owner = Some(metadata.uid().to_string());
+            entry.group = Some(metadata.gid().to_string());
+        }
+        entry
+    }
+}
+
+/// Fetch and parse the listing of `path`. MLSD is used when the server
+/// advertises it, with LIST as the fallback, e.g. when `path` is a file.
+/// MLSD always includes hidden entries; LIST only does with `hidden`, which
+/// sends `LIST -a` as Unix servers expect and plain LIST if that is refused.
+pub fn list<T: IsFtpStream>(fs: &mut T, path: &str, hidden: bool) -> FtpResult<Vec<Entry>> {
+    if client::has_feature(fs, "MLST") {
+        match fs.mlsd(Some(path)) {
+            Ok(lines) => {
+                return Ok(lines
+                    .iter()
+                    .filter_map(|l| Entry::from_mlsx_line(l))
+                    .collect())
+            }
+            Err(FtpError::UnexpectedResponse(r)) if r.status.code() >= 500 => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    let lines = if hidden {
+        match fs.list(Some(&format!("-a {}", path))) {
+            Err(FtpError::UnexpectedResponse(r)) if r.status.code() >= 500 => fs.list(Some(path))?,
+            lines => lines?,
+        }
+    } else {
+        fs.list(Some(path))?
+    };
+    Ok(lines.iter().filter_map(|l| Entry::from_list_line(l)).collect())
+}
+
+/// List a directory on the local machine the way `list` does a remote one,
+/// so both can be shown alike. A file lists as just itself.
+pub fn list_local(path: &Path) -> io::Result<Vec<Entry>> {
+    let metadata = path.symlink_metadata()?;
+    if !metadata.is_dir() {
+        return Ok(vec![Entry::from_local(&path.display().to_string(), path, &metadata)]);
+    }
+
+    let mut entries = Vec::new();
+    for dir_entry in std::fs::read_dir(path)? {
+        let dir_entry = dir_entry?;
+        let name = dir_entry.file_name().to_string_lossy().into_owned();
+        entries.push(Entry::from_local(&name, &dir_entry.path(), &dir_entry.metadata()?));
+    }
+    Ok(entries)
+}
+
+/// Path of `name` inside the remote directory `dir`
+pub fn join(dir: &str, name: &str) -> String {
+    match dir {
+        "." | "" => name.to_string(),
+        d if d.ends_with('/') => format!("{}{}", d, name),
+        d => format!("{}/{}", d, name),
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum SortKey {
+    #[default]
+    Name,
+    Size,
+    Time,
+}
+
+/// How `ls` should filter, order and lay out a listing
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ListOptions {
+    pub long: bool,
+    pub one_per_line: bool,
+    pub all: bool,
+    pub human: bool,
+    pub sort: SortKey,
+    pub reverse: bool,
+}
+
+impl ListOptions {
+    /// Apply a flag group such as `-lh`, returning the first unknown letter
+    pub fn parse_flags(&mut self, flags: &str) -> Result<(), char> {
+        for c in flags.trim_start_matches('-').chars() {
+            match c {
+                'l' => self.long = true,
+                '1' => self.one_per_line = true,
+                'a' => self.all = true,
+                'h' => self.human = true,
+                'S' => self.sort = SortKey::Size,
+                't' => self.sort = SortKey::Time,
+                'r' => self.reverse = true,
+                _ => return Err(c),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Drop hidden entries unless asked for, then order what is left: by name,
+/// or largest/newest first as `ls -S`/`ls -t` do
+pub fn arrange(mut entries: Vec<Entry>, opts: &ListOptions) -> Vec<Entry> {
+    if !opts.all {
+        entries.retain(|e| !e.name.starts_with('.'));
+    }
+    entries.sort_by(|a, b| match opts.sort {
+        SortKey::Name => a.name.cmp(&b.name),
+        SortKey::Size => b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)),
+        SortKey::Time => b.modified.cmp(&a.modified).then_with(|| a.name.cmp(&b.name)),
+    });
+    if opts.reverse {
+        entries.reverse();
+    }
+    entries
+}
+
+/// Render entries the way `ls` would with the given options
+pub fn render(entries: &[Entry], opts: &ListOptions) -> Vec<String> {
+    if opts.long {
+        render_long(entries, opts)
+    } else if opts.one_per_line {
+        entries.iter().map(paint_name).collect()
+    } else {
+        render_grid(entries)
+    }
+}
+
+/// Aligned `ls -l` style columns
+fn render_long(entries: &[Entry], opts: &ListOptions) -> Vec<String> {
+    let rows = entries
+        .iter()
+        .map(|e| {
+            [
+                format!(
+                    "{}{}",
+                    e.kind.as_char(),
+                    e.permissions.as_deref().unwrap_or("?????????")
+                ),
+                e.owner.clone().unwrap_or_else(|| "-".to_string()),
+                e.group.clone().unwrap_or_else(|| "-".to_string()),
+                match e.size {
+                    Some(s) if opts.human => human_size(s),
+                    Some(s) => s.to_string(),
+                    None => "-".to_string(),
+                },
+                e.modified.map(format_time).unwrap_or_else(|| "-".to_string()),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    let mut widths = [0; 5];
+    for row in &rows {
+        for (w, col) in widths.iter_mut().zip(row) {
+            *w = (*w).max(col.chars().count());
+        }
+    }
+
+    rows.iter()
+        .zip(entries)
+        .map(|(row, e)| {
+            let mut line = format!(
+                "{}  {:<ow$}  {:<gw$}  {:>sw$}  {:<tw$}  {}",
+                row[0],
+                row[1],
+                row[2],
+                row[3],
+                row[4],
+                paint_name(e),
+                ow = widths[1],
+                gw = widths[2],
+                sw = widths[3],
+                tw = widths[4],
+            );
+            if let Some(target) = &e.target {
+                line.push_str(&format!(" -> {}", target));
+            }
+            line
+        })
+        .collect()
+}
+
+/// Names laid out column-major across the terminal, like plain `ls`
+fn render_grid(entries: &[Entry]) -> Vec<String> {
+    if entries.is_empty() {
+        return Vec::new();
+    }
+    let term_width = std::env::var("COLUMNS")
+        .ok()
+        .and_then(|c| c.parse::<usize>().ok())
+        .unwrap_or(80);
+    let width = entries
+        .iter()
+        .map(|e| e.name.chars().count())
+        .max()
+        .unwrap_or(0)
+        + 2;
+    let columns = (term_width / width).max(1);
+    let rows = entries.len().div_ceil(columns);
+
+    (0..rows)
+        .map(|row| {
+            let mut line = String::new();
+            for e in entries.iter().skip(row).step_by(rows) {
+                let pad = width - e.name.chars().count();
+                line.push_str(&format!("{}{}", paint_name(e), " ".repeat(pad)));
+            }
+            line.trim_end().to_string()
+        })
+        .collect()
+}
+
+/// Colour a name by type: directories blue, symlinks cyan, executables green
+pub fn paint_name(e: &Entry) -> String {
+    let executable = e
+        .permissions
+        .as_deref()
+        .map(|p| p.contains(['x', 's', 't']))
+        .unwrap_or(false);
+    match e.kind {
+        EntryKind::Directory => Color::Blue.bold().paint(e.name.as_str()).to_string(),
+        EntryKind::Symlink => Color::Cyan.bold().paint(e.name.as_str()).to_string(),
+        EntryKind::File if executable => Color::Green.bold().paint(e.name.as_str()).to_string(),
+        _ => e.name.clone(),
+    }
+}
+
+/// `1536` -> `1.5K`, in powers of 1024 like `ls -h`
+pub fn human_size(size: u64) -> String {
+    const UNITS: [&str; 6] = ["K", "M", "G", "T", "P", "E"];
+    if size < 1024 {
+        return size.to_string();
+    }
+    let mut value = size as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if value < 10.0 {
+        format!("{:.1}{}", value, UNITS[unit])
+    } else {
+        format!("{:.0}{}", value, UNITS[unit])
+    }
+}
+
+/// `ls` style: time of day for recent entries, the year for older ones
+fn format_time(t: NaiveDateTime) -> String {
+    if (Utc::now().naive_utc() - t).num_days().abs() < 180 {
+        t.format("%b %e %H:%M").to_string()
+    } else {
+        t.format("%b %e  %Y").to_string()
+    }
+}
+
+/// Whitespace separated fields along with their byte offsets in `line`
+fn fields(line: &str) -> Vec<(usize, &str)> {
+    line.split_whitespace()
+        .map(|f| (f.as_ptr() as usize - line.as_ptr() as usize, f))
+        .collect()
+}
+
+fn month(name: &str) -> Option<u32> {
+    const MONTHS: [&str; 12] = [
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+    ];
+    let name = name.to_ascii_lowercase();
+    MONTHS
+        .iter()
+        .position(|m| *m == name)
+        .map(|i| i as u32 + 1)
+}
+
+/// Unix listings give either `HH:MM` for the last six months, with the year
+/// left implied, or the year for anything older
+fn unix_date(month_name: &str, day: &str, time_or_year: &str) -> Option<NaiveDateTime> {
+    unix_date_at(month_name, day, time_or_year, Utc::now().naive_utc())
+}
+
+/// `unix_date` as of `now`, which decides the implied year
+fn unix_date_at(month_name: &str, day: &str, time_or_year: &str, now: NaiveDateTime) -> Option<NaiveDateTime> {
+    let month = month(month_name)?;
+    let day = day.parse().ok()?;
+    match NaiveTime::parse_from_str(time_or_year, "%H:%M") {
+        // The latest such date that is not in the future, give or take a day
+        // of time zones; Feb 29 skips years that do not have one
+        Ok(time) => [now.year(), now.year() - 1]
+            .into_iter()
+            .filter_map(|year| NaiveDate::from_ymd_opt(year, month, day))
+            .map(|date| date.and_time(time))
+            .find(|date| *date <= now + Duration::days(1)),
+        Err(_) => Some(
+            NaiveDate::from_ymd_opt(time_or_year.parse().ok()?, month, day)?
+                .and_hms_opt(0, 0, 0)?,
+        ),
+    }
+}
+
+/// `0o755` -> `rwxr-xr-x`
+fn mode_string(mode: u32) -> String {
+    let mut s = String::with_capacity(9);
+    for shift in [6, 3, 0] {
+        let bits = (mode >> shift) & 0o7;
+        s.push(if bits & 4 != 0 { 'r' } else { '-' });
+        s.push(if bits & 2 != 0 { 'w' } else { '-' });
+        s.push(if bits & 1 != 0 { 'x' } else { '-' });
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(y: i32, m: u32, d: u32, h: u32, min: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap().and_hms_opt(h, min, 0).unwrap()
+    }
+
+    fn list_line(line: &str) -> Entry {
+        Entry::from_list_line(line).unwrap()
+    }
+
+    #[test]
+    fn parses_vsftpd_lines() {
+        let e = list_line("-rw-r--r--    1 1000     1000         1234 Jan 05  2021 report.txt");
+        assert_eq!(e.name, "report.txt");
+        assert_eq!(e.kind, EntryKind::File);
+        assert_eq!(e.size, Some(1234));
+        assert_eq!(e.modified, Some(at(2021, 1, 5, 0, 0)));
+        assert_eq!(e.permissions.as_deref(), Some("rw-r--r--"));
+        assert_eq!(e.owner.as_deref(), Some("1000"));
+        assert_eq!(e.group.as_deref(), Some("1000"));
+
+        let e = list_line("drwxr-xr-x    2 0        0            4096 Oct 19 06:34 pub");
+        assert_eq!((e.name.as_str(), e.kind), ("pub", EntryKind::Directory));
+        let modified = e.modified.unwrap();
+        assert_eq!((modified.month(), modified.day()), (10, 19));
+        assert_eq!(modified.time(), NaiveTime::from_hms_opt(6, 34, 0).unwrap());
+    }
+
+    #[test]
+    fn parses_symlinks() {
+        let e = list_line("lrwxrwxrwx    1 0        0              11 Mar 02  2020 latest -> releases/v2");
+        assert_eq!(e.kind, EntryKind::Symlink);
+        assert_eq!(e.name, "latest");
+        assert_eq!(e.target.as_deref(), Some("releases/v2"));
+    }
+
+    #[test]
+    fn parses_proftpd_names_with_spaces() {
+        let e = list_line("-rw-r--r--   1 ftp      ftp        524288 Feb  3  2019 my  file.bin");
+        assert_eq!(e.name, "my  file.bin");
+        assert_eq!(e.size, Some(524288));
+        assert_eq!(e.owner.as_deref(), Some("ftp"));
+        assert_eq!(e.group.as_deref(), Some("ftp"));
+    }
+
+    #[test]
+    fn parses_lines_with_fields_missing() {
+        let e = list_line("-rw-r--r--   1 owner           5 Mar  3  2022 nogroup.txt");
+        assert_eq!(e.name, "nogroup.txt");
+        assert_eq!(e.size, Some(5));
+        assert_eq!(e.owner.as_deref(), Some("owner"));
+        assert_eq!(e.group, None);
+
+        let e = list_line("-rw-r--r-- owner group 12 Mar  3  2022 nolinks");
+        assert_eq!(e.name, "nolinks");
+        assert_eq!(e.size, Some(12));
+        assert_eq!(e.owner.as_deref(), Some("owner"));
+        assert_eq!(e.group.as_deref(), Some("group"));
+    }
+
+    #[test]
+    fn skips_totals_and_dot_entries() {
+        assert!(Entry::from_list_line("total 12").is_none());
+        assert!(Entry::from_list_line("drwxr-xr-x    2 0        0            4096 Jan 05  2021 .").is_none());
+        assert!(Entry::from_list_line("drwxr-xr-x    2 0        0            4096 Jan 05  2021 ..").is_none());
+    }
+
+    #[test]
+    fn keeps_unknown_lines_verbatim() {
+        let e = list_line("something the parser has never seen");
+        assert_eq!(e.kind, EntryKind::Other);
+        assert_eq!(e.name, "something the parser has never seen");
+    }
+
+    #[test]
+    fn parses_iis_lines() {
+        let e = list_line("10-19-20  03:19PM       <DIR>          aspnet_client");
+        assert_eq!((e.name.as_str(), e.kind), ("aspnet_client", EntryKind::Directory));
+        assert_eq!(e.modified, Some(at(2020, 10, 19, 15, 19)));
+
+        let e = list_line("01-02-21  12:05AM                   10 midnight.txt");
+        assert_eq!(e.modified, Some(at(2021, 1, 2, 0, 5)));
+
+        let e = list_line("10-19-2020  15:19              1,234 web config.xml");
+        assert_eq!((e.name.as_str(), e.kind), ("web config.xml", EntryKind::File));
+        assert_eq!(e.size, Some(1234));
+        assert_eq!(e.modified, Some(at(2020, 10, 19, 15, 19)));
+    }
+
+    #[test]
+    fn parses_mlsd_lines() {
+        let e = Entry::from_mlsx_line("type=file;size=1024;modify=20201019063400;UNIX.mode=0644;UNIX.owner=ftp; read me.txt")
+            .unwrap();
+        assert_eq!((e.name.as_str(), e.kind), ("read me.txt", EntryKind::File));
+        assert_eq!(e.size, Some(1024));
+        assert_eq!(e.modified, Some(at(2020, 10, 19, 6, 34)));
+        assert_eq!(e.permissions.as_deref(), Some("rw-r--r--"));
+        assert_eq!(e.owner.as_deref(), Some("ftp"));
+
+        let e = Entry::from_mlsx_line("type=dir;modify=20201019063400.123; pub").unwrap();
+        assert_eq!((e.name.as_str(), e.kind), ("pub", EntryKind::Directory));
+        assert_eq!(e.modified, Some(at(2020, 10, 19, 6, 34)));
+
+        let e = Entry::from_mlsx_line("type=OS.unix=slink:/usr/share;modify=20201019063400; share").unwrap();
+        assert_eq!(e.kind, EntryKind::Symlink);
+        assert_eq!(e.target.as_deref(), Some("/usr/share"));
+
+        assert!(Entry::from_mlsx_line("type=cdir;modify=20201019063400; .").is_none());
+        assert!(Entry::from_mlsx_line("type=pdir;modify=20201019063400; ..").is_none());
+    }
+
+    #[test]
+    fn infers_the_year_of_recent_dates() {
+        let now = at(2025, 1, 10, 12, 0);
+        assert_eq!(unix_date_at("Jan", "9", "08:00", now), Some(at(2025, 1, 9, 8, 0)));
+        // Within a day ahead is this year, as the server may be in another time zone
+        assert_eq!(unix_date_at("Jan", "11", "08:00", now), Some(at(2025, 1, 11, 8, 0)));
+        // Further ahead can only be last year
+        assert_eq!(unix_date_at("Dec", "31", "23:59", now), Some(at(2024, 12, 31, 23, 59)));
+        assert_eq!(unix_date_at("Dec", "31", "2019", now), Some(at(2019, 12, 31, 0, 0)));
+    }
+
+    #[test]
+    fn infers_the_year_of_feb_29() {
+        assert_eq!(
+            unix_date_at("Feb", "29", "10:00", at(2024, 3, 15, 0, 0)),
+            Some(at(2024, 2, 29, 10, 0))
+        );
+        assert_eq!(
+            unix_date_at("Feb", "29", "10:00", at(2025, 1, 10, 0, 0)),
+            Some(at(2024, 2, 29, 10, 0))
+        );
+        assert_eq!(unix_date_at("Feb", "30", "10:00", at(2025, 1, 10, 0, 0)), None);
+    }
+}
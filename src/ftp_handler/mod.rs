@@ -1,2 +1,12 @@
+pub mod checksum;
 pub mod client;
 pub mod commands;
+pub mod conflict;
+pub mod filter;
+pub mod listing;
+pub mod proxy;
+pub mod retry;
+pub mod session;
+pub mod settings;
+pub mod trace;
+pub mod transfer;
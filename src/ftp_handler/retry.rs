@@ -0,0 +1,150 @@
+use crate::ftp_handler::checksum;
+use ansi_term::Color;
+use std::collections::hash_map::RandomState;
+use std::error::Error;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, Read, Write};
+use std::thread;
+use std::time::Duration;
+use suppaftp::{FtpError, FtpResult};
+
+/// The longest wait between two attempts, however many there were before
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// How often, and how patiently, to repeat an operation that failed for a
+/// reason that may go away on its own
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    pub retries: u32,
+    pub delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Run `op`, retrying transient failures with exponential backoff
+    pub fn run<R>(&self, mut op: impl FnMut() -> FtpResult<R>) -> FtpResult<R> {
+        let mut result = op();
+        for attempt in 0..self.retries {
+            match &result {
+                Err(e) if is_transient(e) => {
+                    let wait = self.backoff(attempt);
+                    println!(
+                        "{}",
+                        Color::Yellow.paint(format!(
+                            "[*] {}, retrying in {:.1}s ({}/{})",
+                            e,
+                            wait.as_secs_f64(),
+                            attempt + 1,
+                            self.retries
+                        ))
+                    );
+                    thread::sleep(wait);
+                }
+                _ => break,
+            }
+            result = op();
+        }
+        result
+    }
+
+    /// Delay before retry number `attempt`: doubles every time up to
+    /// `MAX_BACKOFF`, with up to 50% random jitter so that parallel clients
+    /// do not retry in lockstep
+    fn backoff(&self, attempt: u32) -> Duration {
+        let base = self
+            .delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(MAX_BACKOFF);
+        let jitter = RandomState::new().build_hasher().finish() % 1000;
+        base.saturating_add(base / 2000 * jitter as u32)
+    }
+}
+
+/// Whether an error is worth retrying. Network errors and 4xx replies
+/// (RFC 959 "transient negative completion") are; local, permanent and
+/// checksum errors and everything else will fail the same way again.
+pub fn is_transient(e: &FtpError) -> bool {
+    match e {
+        FtpError::ConnectionError(e) => {
+            !is_local(e) && !is_permanent(e) && !checksum::is_mismatch(e)
+        }
+        FtpError::UnexpectedResponse(r) => (400..500).contains(&r.status.code()),
+        _ => false,
+    }
+}
+
+/// A failure on this machine, such as a full disk, rather than on the
+/// network. suppaftp only has `ConnectionError` for I/O errors, so it
+/// travels inside one, keeping the kind of the original error.
+#[derive(Debug)]
+struct LocalError(io::Error);
+
+impl fmt::Display for LocalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl Error for LocalError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.0)
+    }
+}
+
+/// A failure that looks like an I/O error but that retrying will not fix,
+/// such as a proxy refusing the credentials
+#[derive(Debug)]
+struct Permanent(String);
+
+impl fmt::Display for Permanent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for Permanent {}
+
+pub fn permanent(kind: io::ErrorKind, msg: impl Into<String>) -> io::Error {
+    io::Error::new(kind, Permanent(msg.into()))
+}
+
+pub fn is_permanent(e: &io::Error) -> bool {
+    e.get_ref().is_some_and(|inner| inner.is::<Permanent>())
+}
+
+/// Mark an I/O error as local
+pub fn local(e: io::Error) -> io::Error {
+    if is_local(&e) {
+        return e;
+    }
+    io::Error::new(e.kind(), LocalError(e))
+}
+
+/// A local I/O error as an `FtpError`, for use with `map_err`
+pub fn local_error(e: io::Error) -> FtpError {
+    FtpError::ConnectionError(local(e))
+}
+
+pub fn is_local(e: &io::Error) -> bool {
+    e.get_ref().is_some_and(|inner| inner.is::<LocalError>())
+}
+
+/// A local file or pipe whose errors are marked local, so that they can be
+/// told apart from network errors once suppaftp has passed them on
+pub struct Local<'a, T>(pub &'a mut T);
+
+impl<T: Read> Read for Local<'_, T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf).map_err(local)
+    }
+}
+
+impl<T: Write> Write for Local<'_, T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf).map_err(local)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush().map_err(local)
+    }
+}
@@ -0,0 +1,311 @@
+use crate::ftp_handler::client::{self, ConnectionInfo, IsFtpStream};
+use crate::ftp_handler::retry;
+use crate::ftp_handler::trace;
+use chrono::NaiveDateTime;
+use std::io::{ErrorKind, Read};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use suppaftp::types::{Features, FileType, Mode, Response};
+use suppaftp::{FtpError, FtpResult, Status};
+
+/// A logged in FTP stream that remembers how it was established, so that it
+/// can be transparently re-established when the control connection drops.
+pub struct Session<T: IsFtpStream> {
+    stream: T,
+    info: ConnectionInfo,
+    last_pwd: Option<String>,
+    lost: bool,
+    last_used: Instant,
+    features: Option<Features>,
+    system: Option<String>,
+    /// The TYPE last set on the current connection
+    file_type: Option<FileType>,
+}
+
+impl<T: IsFtpStream> Session<T> {
+    pub fn host(&self) -> &str {
+        self.info.host.as_str()
+    }
+
+    pub fn user(&self) -> &str {
+        self.info.username.as_str()
+    }
+
+    /// Whether a command since the last reconnect failed because the
+    /// control connection is gone
+    pub fn connection_lost(&self) -> bool {
+        self.lost
+    }
+
+    /// Connect and log in again with the stored credentials, then go back to
+    /// the last known working directory
+    pub fn reconnect(&mut self) -> FtpResult<()> {
+        let retry = self.info.retry;
+        retry.run(|| self.reopen())
+    }
+
+    fn reopen(&mut self) -> FtpResult<()> {
+        self.stream = T::open(&self.info)?;
+        self.lost = false;
+        self.file_type = None;
+        // The server may have been upgraded or failed over meanwhile
+        self.features = None;
+        self.negotiate()?;
+        if let Some(pwd) = self.last_pwd.clone() {
+            self.stream.cwd(pwd)?;
+        }
+        Ok(())
+    }
+
+    /// Learn what the server supports, once per session, and set up every
+    /// new connection to make use of it
+    fn negotiate(&mut self) -> FtpResult<()> {
+        if self.system.is_none() {
+            self.system = match self.stream.system() {
+                Ok(system) => Some(system),
+                Err(FtpError::UnexpectedResponse(_)) => None,
+                Err(e) => return Err(e),
+            };
+        }
+        // EPSV reuses the control connection's address, which NAT cannot
+        // rewrite wrongly, but which is the proxy's when tunnelling
+        if self.info.proxy.is_none() && client::has_feature(self, "EPSV") {
+            self.stream.set_mode(Mode::ExtendedPassive);
+        }
+        // Servers start out in ASCII mode, which would mangle binary files
+        self.transfer_type(FileType::Binary)
+    }
+
+    /// Time since the last command was sent on the control connection
+    pub fn idle_time(&self) -> Duration {
+        self.last_used.elapsed()
+    }
+
+    fn track<R>(&mut self, result: FtpResult<R>) -> FtpResult<R> {
+        self.last_used = Instant::now();
+        if let Err(e) = &result {
+            self.lost = match e {
+                // A late reply would leave the control channel out of step
+                e if client::is_timeout(e) => true,
+                FtpError::ConnectionError(e) if retry::is_local(e) => false,
+                FtpError::ConnectionError(e) => matches!(
+                    e.kind(),
+                    ErrorKind::BrokenPipe
+                        | ErrorKind::ConnectionReset
+                        | ErrorKind::ConnectionAborted
+                        | ErrorKind::NotConnected
+                        | ErrorKind::UnexpectedEof
+                ),
+                FtpError::UnexpectedResponse(r) => r.status == Status::NotAvailable,
+                // An empty read from a closed socket shows up as a malformed reply
+                FtpError::BadResponse => self.stream.noop().is_err(),
+                _ => false,
+            };
+        }
+        result
+    }
+}
+
+impl<T: IsFtpStream> IsFtpStream for Session<T> {
+    fn open(info: &ConnectionInfo) -> FtpResult<Self> {
+        let mut session = Session {
+            stream: info.retry.run(|| T::open(info))?,
+            info: info.clone(),
+            last_pwd: None,
+            lost: false,
+            last_used: Instant::now(),
+            features: None,
+            system: None,
+            file_type: None,
+        };
+        session.negotiate()?;
+        Ok(session)
+    }
+    fn pwd(&mut self) -> FtpResult<String> {
+        let r = self.stream.pwd();
+        if let Ok(pwd) = &r {
+            self.last_pwd = Some(pwd.clone());
+        }
+        self.track(r)
+    }
+    fn cdup(&mut self) -> FtpResult<()> {
+        let r = self.stream.cdup();
+        self.track(r)
+    }
+    fn quit(&mut self) -> FtpResult<()> {
+        let r = self.stream.quit();
+        self.track(r)
+    }
+    fn noop(&mut self) -> FtpResult<()> {
+        let r = self.stream.noop();
+        self.track(r)
+    }
+    fn cwd<S: AsRef<str>>(&mut self, path: S) -> FtpResult<()> {
+        let r = self.stream.cwd(path);
+        self.track(r)
+    }
+    fn mkdir<S: AsRef<str>>(&mut self, pathname: S) -> FtpResult<()> {
+        let r = self.stream.mkdir(pathname);
+        self.track(r)
+    }
+    fn list(&mut self, pathname: Option<&str>) -> FtpResult<Vec<String>> {
+        let r = self.stream.list(pathname);
+        self.track(r)
+    }
+    fn mlsd(&mut self, pathname: Option<&str>) -> FtpResult<Vec<String>> {
+        let r = self.stream.mlsd(pathname);
+        self.track(r)
+    }
+    /// FEAT is asked once and remembered; a server that does not know the
+    /// command is taken to have no optional features
+    fn feat(&mut self) -> FtpResult<Features> {
+        if let Some(features) = &self.features {
+            return Ok(features.clone());
+        }
+        let features = match self.stream.feat() {
+            Ok(features) => features,
+            Err(FtpError::UnexpectedResponse(_)) => Features::new(),
+            Err(e) => return self.track(Err(e)),
+        };
+        self.features = Some(features.clone());
+        Ok(features)
+    }
+    fn size<S: AsRef<str>>(&mut self, pathname: S) -> FtpResult<usize> {
+        let r = self.stream.size(pathname);
+        self.track(r)
+    }
+    fn mdtm<S: AsRef<str>>(&mut self, pathname: S) -> FtpResult<NaiveDateTime> {
+        let r = self.stream.mdtm(pathname);
+        self.track(r)
+    }
+    fn append_file<R: Read>(&mut self, filename: &str, r: &mut R) -> FtpResult<u64> {
+        let r = self.stream.append_file(filename, r);
+        self.track(r)
+    }
+
+    fn rm<S: AsRef<str>>(&mut self, filename: S) -> FtpResult<()> {
+        let r = self.stream.rm(filename);
+        self.track(r)
+    }
+    fn rmdir<S: AsRef<str>>(&mut self, pathname: S) -> FtpResult<()> {
+        let r = self.stream.rmdir(pathname);
+        self.track(r)
+    }
+    fn rename<S: AsRef<str>>(&mut self, from_name: S, to_name: S) -> FtpResult<()> {
+        let r = self.stream.rename(from_name, to_name);
+        self.track(r)
+    }
+    fn retr<F, D>(&mut self, file_name: &str, reader: F) -> FtpResult<D>
+    where
+        F: FnMut(&mut dyn Read) -> FtpResult<D>,
+    {
+        let r = self.stream.retr(file_name, reader);
+        self.track(r)
+    }
+    fn retr_until<F>(&mut self, file_name: &str, reader: F) -> FtpResult<()>
+    where
+        F: FnMut(&mut dyn Read) -> FtpResult<bool>,
+    {
+        let r = self.stream.retr_until(file_name, reader);
+        self.track(r)
+    }
+    fn put_file<S: AsRef<str>, R: Read>(&mut self, filename: S, r: &mut R) -> FtpResult<u64> {
+        let r = self.stream.put_file(filename, r);
+        self.track(r)
+    }
+
+    fn login<S: AsRef<str>>(&mut self, user: S, password: S) -> FtpResult<()> {
+        let r = self.stream.login(user.as_ref(), password.as_ref());
+        if r.is_ok() {
+            self.info.username = user.as_ref().to_string();
+            self.info.password = password.as_ref().to_string();
+            // Features and the TYPE may differ from one user to the next
+            self.features = None;
+            self.file_type = None;
+        }
+        self.track(r)
+    }
+    fn custom_command(&mut self, command: &str, expected: &[Status]) -> FtpResult<Response> {
+        let r = self.stream.custom_command(command, expected);
+        self.track(r)
+    }
+    fn quote(&mut self, command: &str) -> FtpResult<Response> {
+        // Nothing known about the connection can be trusted afterwards
+        self.features = None;
+        self.file_type = None;
+        let r = self.stream.quote(command);
+        self.track(r)
+    }
+    fn set_mode(&mut self, mode: Mode) {
+        self.stream.set_mode(mode)
+    }
+    fn transfer_type(&mut self, file_type: FileType) -> FtpResult<()> {
+        if self.file_type.as_ref() == Some(&file_type) {
+            return Ok(());
+        }
+        let r = self.stream.transfer_type(file_type.clone());
+        if r.is_ok() {
+            self.file_type = Some(file_type);
+        }
+        self.track(r)
+    }
+    fn resume_transfer(&mut self, offset: usize) -> FtpResult<()> {
+        let r = self.stream.resume_transfer(offset);
+        self.track(r)
+    }
+    /// Asked when the session is opened, like FEAT
+    fn system(&mut self) -> FtpResult<String> {
+        if let Some(system) = &self.system {
+            return Ok(system.clone());
+        }
+        let r = self.stream.system();
+        if let Ok(system) = &r {
+            self.system = Some(system.clone());
+        }
+        self.track(r)
+    }
+
+    fn retrying<R, F>(&mut self, mut op: F) -> FtpResult<R>
+    where
+        F: FnMut(&mut Self) -> FtpResult<R>,
+    {
+        let retry = self.info.retry;
+        retry.run(|| {
+            if self.lost {
+                self.reopen()?;
+            }
+            op(self)
+        })
+    }
+}
+
+/// Send a NOOP whenever the session has been idle for `interval`, so that
+/// server idle timers do not drop us while the user sits at the prompt.
+/// Commands hold the session lock while they run, so a NOOP is never sent
+/// in the middle of one. Its traffic is not shown, as it comes at any time.
+pub fn keep_alive<T>(session: Arc<Mutex<Session<T>>>, interval: Duration)
+where
+    T: IsFtpStream + Send + 'static,
+{
+    thread::spawn(move || {
+        trace::mute_thread();
+        loop {
+            let wait = match session.lock() {
+                Ok(mut session) => {
+                    let idle = session.idle_time();
+                    if idle < interval {
+                        interval - idle
+                    } else {
+                        if !session.connection_lost() {
+                            let _ = session.noop();
+                        }
+                        interval
+                    }
+                }
+                Err(_) => return,
+            };
+            thread::sleep(wait);
+        }
+    });
+}
@@ -0,0 +1,24 @@
+use crate::ftp_handler::conflict::ConflictPolicy;
+use crate::ftp_handler::transfer::TransferMode;
+use std::path::PathBuf;
+
+/// Client side preferences that affect how commands behave, set from the
+/// command line and adjustable from the prompt
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// Carry modification times across with downloaded and uploaded files
+    pub preserve_time: bool,
+    /// Copy the permission bits of uploaded files with SITE CHMOD
+    pub preserve_mode: bool,
+    /// Upload under a temporary name and rename into place when done
+    pub atomic: bool,
+    /// Compare checksums with the server after every transfer
+    pub verify: bool,
+    pub transfer_mode: TransferMode,
+    /// What `get` and `put` do about files that are in the way
+    pub conflict: ConflictPolicy,
+    /// Show the local working directory in the prompt as well
+    pub show_local_dir: bool,
+    /// Where `lcd -` goes back to
+    pub previous_local_dir: Option<PathBuf>,
+}
@@ -0,0 +1,213 @@
+use ansi_term::Color;
+use chrono::Local;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::cell::Cell;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+/// Shows the control channel dialogue. suppaftp traces every line it sends
+/// and receives through the `log` crate, so this is a logger that picks those
+/// records out and prints them as they went over the wire. The records are
+/// suppaftp's private debugging text, so the tests below check that they
+/// still read as expected.
+struct Tracer {
+    enabled: AtomicBool,
+    log_file: Mutex<Option<File>>,
+    /// suppaftp traces the first line of a reply twice, once before and once
+    /// after parsing its code; set in between so the repeat can be dropped
+    repeat_pending: AtomicBool,
+}
+
+static TRACER: Tracer = Tracer {
+    enabled: AtomicBool::new(false),
+    log_file: Mutex::new(None),
+    repeat_pending: AtomicBool::new(false),
+};
+
+/// Install the tracer, showing traffic from the start if `enabled`. All of
+/// it is appended to `log_file`, if one is given, whether shown or not.
+pub fn init(enabled: bool, log_file: Option<&Path>) -> std::io::Result<()> {
+    if let Some(path) = log_file {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        *TRACER.log_file.lock().unwrap() = Some(file);
+    }
+    set_enabled(enabled);
+    // Only fails if a logger is already installed, in which case there is
+    // nothing to do
+    if log::set_logger(&TRACER).is_ok() {
+        log::set_max_level(LevelFilter::Trace);
+    }
+    Ok(())
+}
+
+thread_local! {
+    /// Set on threads whose traffic is only logged to the file, such as the
+    /// keepalive NOOPs, which would otherwise print over the prompt
+    static MUTED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Stop showing the traffic of the current thread
+pub fn mute_thread() {
+    MUTED.with(|muted| muted.set(true));
+}
+
+pub fn set_enabled(enabled: bool) {
+    TRACER.enabled.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    TRACER.enabled.load(Ordering::Relaxed)
+}
+
+impl Log for Tracer {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() == Level::Trace && metadata.target().starts_with("suppaftp")
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let message = record.args().to_string();
+        let (arrow, line) = if let Some(command) = message.strip_prefix("CC OUT: ") {
+            ("->", mask_password(command))
+        } else if let Some(bytes) = message.strip_prefix("CC IN: ") {
+            if self.repeat_pending.swap(false, Ordering::Relaxed) {
+                return;
+            }
+            ("<-", from_byte_list(bytes))
+        } else if let Some(line) = message.strip_prefix("FEAT IN: ") {
+            ("<-", from_debug_str(line))
+        } else if message.starts_with("Code parsed from response") {
+            self.repeat_pending.store(true, Ordering::Relaxed);
+            return;
+        } else {
+            return;
+        };
+
+        let line = line.trim_end_matches(['\r', '\n']);
+        if is_enabled() && !MUTED.with(Cell::get) {
+            println!("{} {}", Color::Purple.dimmed().paint(arrow), Color::White.dimmed().paint(line));
+        }
+        if let Some(file) = self.log_file.lock().unwrap().as_mut() {
+            let _ = writeln!(
+                file,
+                "{} {} {}",
+                Local::now().format("%Y-%m-%d %H:%M:%S%.3f"),
+                arrow,
+                line
+            );
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(file) = self.log_file.lock().unwrap().as_mut() {
+            let _ = file.flush();
+        }
+    }
+}
+
+fn mask_password(command: &str) -> String {
+    match command.get(..5) {
+        Some(verb) if verb.eq_ignore_ascii_case("PASS ") => format!("{}****", verb),
+        _ => command.to_string(),
+    }
+}
+
+/// Reply lines are traced as the `Debug` output of a byte vector,
+/// e.g. `[50, 50, 48, 32, ...]`
+fn from_byte_list(list: &str) -> String {
+    let bytes = list
+        .trim_matches(['[', ']'])
+        .split(", ")
+        .filter_map(|b| b.parse::<u8>().ok())
+        .collect::<Vec<_>>();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// FEAT lines are traced as the `Debug` output of a string
+fn from_debug_str(s: &str) -> String {
+    s.trim_matches('"')
+        .replace("\\r", "\r")
+        .replace("\\n", "\n")
+        .replace("\\\"", "\"")
+        .replace("\\\\", "\\")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::net::{TcpListener, TcpStream};
+    use suppaftp::FtpStream;
+
+    /// A server that answers every command with a canned reply
+    fn serve(listener: TcpListener) {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut stream: TcpStream = stream;
+        stream.write_all(b"220 ready\r\n").unwrap();
+        let mut line = String::new();
+        while reader.read_line(&mut line).unwrap() > 0 {
+            let reply: &[u8] = match line.split_whitespace().next().unwrap_or_default() {
+                "USER" => b"331 password please\r\n",
+                "PASS" => b"230 logged in\r\n",
+                "FEAT" => b"211-Features:\r\n MDTM\r\n SIZE\r\n211 End\r\n",
+                "NOOP" => b"200 ok\r\n",
+                _ => b"221 bye\r\n",
+            };
+            stream.write_all(reply).unwrap();
+            line.clear();
+        }
+    }
+
+    /// The trace is read out of suppaftp's log messages, which are not part
+    /// of its API; this fails if an update of suppaftp changes them
+    #[test]
+    fn traces_the_control_channel() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || serve(listener));
+        let path = std::env::temp_dir().join(format!("rsftp-trace-{}.log", std::process::id()));
+        init(false, Some(&path)).unwrap();
+
+        let mut ftp = FtpStream::connect(address).unwrap();
+        ftp.login("user", "secret").unwrap();
+        ftp.feat().unwrap();
+        ftp.noop().unwrap();
+        ftp.quit().unwrap();
+        drop(ftp);
+        server.join().unwrap();
+        *TRACER.log_file.lock().unwrap() = None;
+
+        let log = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        // Without the date and time
+        let lines = log
+            .lines()
+            .map(|l| l.splitn(3, ' ').nth(2).unwrap_or_default())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            [
+                "<- 220 ready",
+                "-> USER user",
+                "<- 331 password please",
+                "-> PASS ****",
+                "<- 230 logged in",
+                "-> FEAT",
+                "<- 211-Features:",
+                "<-  MDTM",
+                "<-  SIZE",
+                "<- 211 End",
+                "-> NOOP",
+                "<- 200 ok",
+                "-> QUIT",
+                "<- 221 bye",
+            ]
+        );
+    }
+}
@@ -0,0 +1,197 @@
+use std::io::{self, Read, Write};
+use std::path::Path;
+use suppaftp::types::{FileType, FormatControl};
+
+/// How file contents are sent: byte for byte, or as text whose line endings
+/// are converted between the local convention and the CRLF of the wire
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum TransferMode {
+    #[default]
+    Binary,
+    Ascii,
+    /// ASCII for files whose extension says they are text, binary otherwise
+    Auto,
+}
+
+impl TransferMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TransferMode::Binary => "binary",
+            TransferMode::Ascii => "ascii",
+            TransferMode::Auto => "auto",
+        }
+    }
+
+    /// The TYPE to transfer `path` with
+    pub fn file_type(&self, path: &str) -> FileType {
+        match self {
+            TransferMode::Ascii => FileType::Ascii(FormatControl::Default),
+            TransferMode::Auto if is_text(path) => FileType::Ascii(FormatControl::Default),
+            _ => FileType::Binary,
+        }
+    }
+}
+
+const TEXT_EXTENSIONS: &[&str] = &[
+    "txt", "text", "md", "rst", "csv", "tsv", "log", "ini", "cfg", "conf", "json", "xml", "yml",
+    "yaml", "toml", "html", "htm", "css", "js", "sh", "bat", "py", "pl", "rb", "php", "c", "h",
+    "cpp", "hpp", "rs", "go", "java", "sql",
+];
+
+fn is_text(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| TEXT_EXTENSIONS.iter().any(|t| t.eq_ignore_ascii_case(e)))
+}
+
+/// Copy an ASCII mode download, turning the CRLF line endings of the wire
+/// into the local ones
+pub fn copy_to_local<R: Read + ?Sized, W: Write>(reader: &mut R, writer: &mut W) -> io::Result<u64> {
+    if cfg!(windows) {
+        return io::copy(reader, writer);
+    }
+    let mut buf = [0u8; 8192];
+    let mut out = Vec::with_capacity(buf.len());
+    let mut pending_cr = false;
+    let mut written = 0;
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        out.clear();
+        for &b in &buf[..n] {
+            // A CR is held back until it is known whether an LF follows,
+            // which may only arrive with the next read
+            if pending_cr && b != b'\n' {
+                out.push(b'\r');
+            }
+            pending_cr = b == b'\r';
+            if !pending_cr {
+                out.push(b);
+            }
+        }
+        writer.write_all(&out)?;
+        written += out.len() as u64;
+    }
+    if pending_cr {
+        writer.write_all(b"\r")?;
+        written += 1;
+    }
+    Ok(written)
+}
+
+/// Reads a local text file for an ASCII mode upload, turning bare LFs into
+/// the CRLF the wire expects
+pub struct ToNetwork<'a, R: Read> {
+    inner: &'a mut R,
+    buf: Vec<u8>,
+    pos: usize,
+    last_was_cr: bool,
+}
+
+impl<'a, R: Read> ToNetwork<'a, R> {
+    pub fn new(inner: &'a mut R) -> Self {
+        ToNetwork {
+            inner,
+            buf: Vec::new(),
+            pos: 0,
+            last_was_cr: false,
+        }
+    }
+}
+
+impl<R: Read> Read for ToNetwork<'_, R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.buf.len() {
+            let mut chunk = [0u8; 8192];
+            let n = self.inner.read(&mut chunk)?;
+            self.buf.clear();
+            self.pos = 0;
+            for &b in &chunk[..n] {
+                if b == b'\n' && !self.last_was_cr {
+                    self.buf.push(b'\r');
+                }
+                self.buf.push(b);
+                self.last_was_cr = b == b'\r';
+            }
+        }
+        let n = out.len().min(self.buf.len() - self.pos);
+        out[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Hands out one byte per read, so that every pair of bytes is split
+    /// across two reads
+    struct OneByte<'a>(&'a [u8]);
+
+    impl Read for OneByte<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match (self.0.split_first(), buf.first_mut()) {
+                (Some((&b, rest)), Some(out)) => {
+                    *out = b;
+                    self.0 = rest;
+                    Ok(1)
+                }
+                _ => Ok(0),
+            }
+        }
+    }
+
+    fn to_local(input: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let n = copy_to_local(&mut OneByte(input), &mut out).unwrap();
+        assert_eq!(n, out.len() as u64);
+        out
+    }
+
+    fn to_network(input: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        ToNetwork::new(&mut OneByte(input)).read_to_end(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn converts_crlf_to_lf() {
+        assert_eq!(to_local(b"one\r\ntwo\r\n"), b"one\ntwo\n");
+        assert_eq!(to_local(b"\r\n\r\n"), b"\n\n");
+        assert_eq!(to_local(b"\r\r\n"), b"\r\n");
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn keeps_lone_and_trailing_crs() {
+        assert_eq!(to_local(b"a\rb"), b"a\rb");
+        assert_eq!(to_local(b"a\r"), b"a\r");
+        assert_eq!(to_local(b"\r"), b"\r");
+        assert_eq!(to_local(b"a\n"), b"a\n");
+    }
+
+    #[test]
+    fn converts_nothing_from_nothing() {
+        assert_eq!(to_local(b""), b"");
+        assert_eq!(to_network(b""), b"");
+    }
+
+    #[test]
+    fn converts_lf_to_crlf() {
+        assert_eq!(to_network(b"one\ntwo\n"), b"one\r\ntwo\r\n");
+        assert_eq!(to_network(b"\n\n"), b"\r\n\r\n");
+        assert_eq!(to_network(b"no newline"), b"no newline");
+    }
+
+    #[test]
+    fn leaves_existing_crlf_alone() {
+        assert_eq!(to_network(b"one\r\ntwo\n"), b"one\r\ntwo\r\n");
+        assert_eq!(to_network(b"a\rb\r"), b"a\rb\r");
+        assert_eq!(to_network(b"\r\r\n"), b"\r\r\n");
+    }
+}
@@ -1,39 +1,83 @@
 mod cli;
 mod ftp_handler;
 
+use ftp_handler::client::{ConnectionInfo, IsFtpStream};
+use ftp_handler::retry::RetryPolicy;
+use ftp_handler::session::{self, Session};
+use ftp_handler::settings::Settings;
+use ftp_handler::transfer::TransferMode;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use suppaftp::{FtpStream, NativeTlsFtpStream};
+
 fn main() {
     let args = cli::parsing::init();
     println!("{:#?}", args);
 
+    let info = ConnectionInfo {
+        host: args.host,
+        port: args.port,
+        username: args.username,
+        password: args.password,
+        connect_timeout: args.connect_timeout.map(Duration::from_secs),
+        timeout: args.timeout.map(Duration::from_secs),
+        data_timeout: args.data_timeout.map(Duration::from_secs),
+        retry: RetryPolicy {
+            retries: args.retries,
+            delay: Duration::from_secs_f64(args.retry_delay),
+        },
+        proxy: args.proxy,
+    };
+
+    if let Err(e) = ftp_handler::trace::init(args.verbose, args.log_file.as_deref()) {
+        eprintln!("[-] Could not open log file: {}", e);
+        std::process::exit(1);
+    }
+
+    let settings = Settings {
+        preserve_time: !args.no_preserve_time,
+        preserve_mode: args.preserve_mode,
+        atomic: args.atomic,
+        verify: args.verify,
+        transfer_mode: TransferMode::default(),
+        conflict: args.conflict,
+        show_local_dir: args.show_local_dir,
+        previous_local_dir: None,
+    };
+
+    let keepalive = Some(Duration::from_secs(args.keepalive)).filter(|d| !d.is_zero());
+
     if !args.tls {
-        let host = args.host.as_str();
-        let mut current_user = args.username;
-        let mut fs = ftp_handler::client::connect(
-            host.to_string(),
-            args.port,
-            current_user.as_str(),
-            args.password.as_str(),
-        );
-
-        loop {
-            let pwd = fs.pwd().unwrap_or("/".to_string());
-            current_user =
-                cli::prompt::prompt_user(pwd.as_str(), host, current_user.as_str(), &mut fs);
-        }
+        run::<FtpStream>(&info, keepalive, settings);
     } else {
-        let host = args.host.as_str();
-        let mut current_user = args.username;
-        let mut fs = ftp_handler::client::connect_tls(
-            host.to_string(),
-            args.port,
-            current_user.as_str(),
-            args.password.as_str(),
-        );
-
-        loop {
-            let pwd = fs.pwd().unwrap_or("/".to_string());
-            current_user =
-                cli::prompt::prompt_user(pwd.as_str(), host, current_user.as_str(), &mut fs);
+        run::<NativeTlsFtpStream>(&info, keepalive, settings);
+    }
+}
+
+fn run<T: IsFtpStream + Send + 'static>(
+    info: &ConnectionInfo,
+    keepalive: Option<Duration>,
+    mut settings: Settings,
+) {
+    let session = match Session::<T>::open(info) {
+        Ok(session) => session,
+        Err(e) if ftp_handler::client::is_timeout(&e) => {
+            eprintln!("[-] Timed out connecting to {}:{}", info.host, info.port);
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("[-] Could not connect to {}:{}: {}", info.host, info.port, e);
+            std::process::exit(1);
         }
+    };
+
+    let session = Arc::new(Mutex::new(session));
+    if let Some(interval) = keepalive {
+        session::keep_alive(Arc::clone(&session), interval);
+    }
+
+    loop {
+        let pwd = session.lock().unwrap().pwd().unwrap_or("/".to_string());
+        cli::prompt::prompt_user(pwd.as_str(), &session, &mut settings);
     }
 }